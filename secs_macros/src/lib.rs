@@ -6,8 +6,9 @@ use {
         format,
         str::FromStr,
         string::{String, ToString},
+        vec::Vec,
     },
-    proc_macro::{TokenStream, TokenTree},
+    proc_macro::{Delimiter, Spacing, TokenStream, TokenTree},
 };
 
 #[proc_macro]
@@ -15,12 +16,16 @@ pub fn impl_bundle(input: TokenStream) -> TokenStream {
     let mut generics = String::new();
     let mut types = String::new();
     let mut components_impl = String::new();
+    let mut prep_impl = String::new();
+    let mut write_impl = String::new();
     let mut current_component = 0;
 
     for token in input {
         generics += &format!("{token}: Component, ");
         types += &format!("{token},");
         components_impl += &format!("Box::new(self.{current_component}), ");
+        prep_impl += &format!("<{token} as Bundle>::prep_storage(storage, types);");
+        write_impl += &format!("self.{current_component}.write(entity, location, storage);");
         current_component += 1;
     }
 
@@ -34,6 +39,14 @@ pub fn impl_bundle(input: TokenStream) -> TokenStream {
             fn components_from_box(self: Box<Self>) -> Vec<Box<dyn Component>> {{
                 vec![{components_impl}]
             }}
+
+            fn prep_storage(storage: &mut Storage, types: &mut Vec<TypeId>) {{
+                {prep_impl}
+            }}
+
+            fn write(self, entity: usize, location: EntityLocation, storage: &mut Storage) {{
+                {write_impl}
+            }}
         }}
         "
     ))
@@ -42,53 +55,130 @@ pub fn impl_bundle(input: TokenStream) -> TokenStream {
 
 #[proc_macro]
 pub fn impl_system_param_fn(input: TokenStream) -> TokenStream {
-    let mut generics = String::from("<Function, ");
-    let mut types_tuple = String::from("(");
+    let params: Vec<String> = input.into_iter().map(|token| token.to_string()).collect();
+
+    TokenStream::from_str(&format!(
+        "{}\n{}",
+        system_param_fn(&params, false),
+        system_param_fn(&params, true)
+    ))
+    .unwrap()
+}
+
+/// Generates a `SystemParamFn` impl for functions with the given parameters. If `has_input`
+/// is true, the impl is for functions that take an `In<Input>` before those parameters.
+fn system_param_fn(params: &[String], has_input: bool) -> String {
+    let mut generics = String::from("<Function, Out, ");
+    let mut fn_ptr_args = String::new();
+    let mut state_tuple = String::from("(");
+    let mut state_init = String::from("(");
     let mut fn_args = String::new();
     let mut fn_call_args = String::new();
-    let mut variables = String::new();
+    let mut data_tuple = String::from("(");
+    let mut takes = String::from("(");
     let mut releases = String::new();
+    let mut accesses = String::new();
+    let mut needs_world = String::from("false");
 
-    for token in input {
-        generics += &format!("{token}: SystemParam, ");
-        types_tuple += &format!("{token}, ");
-        fn_args += &format!("{token}::Fetch<'_>, ");
-
-        let var_name = token.to_string().to_lowercase();
-        variables += &format!("let mut {var_name} = {token}::Data::take(world);\n");
-        fn_call_args += &format!("{token}::fetch(&mut {var_name}),");
-        releases += &format!("{var_name}.release(world);\n");
+    let input_ty = if has_input {
+        generics += "Input, ";
+        fn_ptr_args += "In<Input>, ";
+        fn_args += "In<Input>, ";
+        fn_call_args += "In(input), ";
+        "Input"
+    } else {
+        "()"
+    };
+
+    for (idx, param) in params.iter().enumerate() {
+        generics += &format!("{param}: SystemParam, ");
+        fn_ptr_args += &format!("{param}, ");
+        state_tuple += &format!("<{param}::Data as WorldData>::State, ");
+        state_init += "Default::default(), ";
+        fn_args += &format!("{param}::Fetch<'_>, ");
+
+        data_tuple += &format!("{param}::Data, ");
+        takes += &format!("{param}::Data::take(world, &mut state.{idx}), ");
+        fn_call_args += &format!("{param}::fetch(&mut data.{idx}, &mut world),");
+        needs_world += &format!(" || {param}::NEEDS_WORLD");
+        releases += &format!("data.{idx}.release(world, &mut state.{idx});\n");
+        accesses += &format!("{param}::access(&mut access);\n");
     }
 
     generics += ">";
-    types_tuple += ")";
+    state_tuple += ")";
+    state_init += ")";
+    data_tuple += ")";
+    takes += ")";
+    // Clippy complains about a function body that's just `()`
+    if params.is_empty() {
+        state_init.clear();
+        takes.clear();
+    }
 
-    TokenStream::from_str(&format!(
+    format!(
         "
-        impl {generics} SystemParamFn<{types_tuple}> for Function
+        impl {generics} SystemParamFn<fn({fn_ptr_args}) -> Out> for Function
         where
-            for <'a> &'a Function: Fn({fn_args}) + Fn{types_tuple},
+            for <'a> &'a Function: Fn({fn_args}) -> Out + Fn({fn_ptr_args}) -> Out,
         {{
-            fn execute(&self, world: &mut World) {{
-                {variables}
-                (&self)({fn_call_args});
+            type In = {input_ty};
+            type Out = Out;
+            type State = {state_tuple};
+            type Data = {data_tuple};
+            const NEEDS_WORLD: bool = {needs_world};
+
+            fn init_state() -> Self::State {{
+                {state_init}
+            }}
+
+            #[allow(unused_mut)]
+            fn access() -> Access {{
+                let mut access = Access::default();
+                {accesses}
+                access
+            }}
+
+            #[allow(unused_variables)]
+            fn take(world: &mut World, state: &mut Self::State) -> Self::Data {{
+                {takes}
+            }}
+
+            #[allow(unused_variables, unused_mut)]
+            fn call(&self, input: Self::In, data: &mut Self::Data, world: Option<&mut World>) -> Out {{
+                let mut world = world;
+                (&self)({fn_call_args})
+            }}
+
+            #[allow(unused_variables)]
+            fn release(data: Self::Data, world: &mut World, state: &mut Self::State) {{
                 {releases}
             }}
         }}
         "
-    ))
-    .unwrap()
+    )
 }
 
-#[proc_macro_derive(Component)]
+/// Derives `Component` for a struct. The component is stored in tables unless the struct has a
+/// `#[component(storage = "SparseSet")]` attribute; see `secs::entity::StorageType`.
+#[proc_macro_derive(Component, attributes(component))]
 pub fn component_derive(input: TokenStream) -> TokenStream {
     let mut source = input.into_iter().peekable();
+    let mut sparse_set = false;
 
-    // Skip over useless tokens
+    // Skip over useless tokens, except for the `component` attribute
     while let Some(token) = source.peek() {
         match token {
             TokenTree::Punct(_) => source.next(),
-            TokenTree::Group(_) => source.next(),
+            TokenTree::Group(group) => {
+                let mut attribute = group.stream().into_iter();
+                if let Some(TokenTree::Ident(ident)) = attribute.next() {
+                    if ident.to_string() == "component" {
+                        sparse_set = parse_storage_attribute(attribute);
+                    }
+                }
+                source.next()
+            }
             TokenTree::Ident(ident) => match ident.to_string().as_str() {
                 "pub" => source.next(),
                 "struct" => source.next(),
@@ -100,6 +190,13 @@ pub fn component_derive(input: TokenStream) -> TokenStream {
 
     // Get the struct's name
     let struct_name = source.next().unwrap().to_string();
+    let storage_type = if sparse_set {
+        "fn storage_type() -> secs::entity::StorageType {
+            secs::entity::StorageType::SparseSet
+        }"
+    } else {
+        ""
+    };
 
     TokenStream::from_str(&format!(
         "
@@ -107,8 +204,214 @@ pub fn component_derive(input: TokenStream) -> TokenStream {
             fn prep_storage(&self, storage: &mut secs::world::storage::Storage) {{
                 storage.prep_for::<Self>();
             }}
+            {storage_type}
         }}
         "
     ))
     .unwrap()
 }
+
+/// Parses the inside of a `#[component(...)]` attribute, after `component`. Returns true if it
+/// selects sparse set storage.
+fn parse_storage_attribute(mut attribute: impl Iterator<Item = TokenTree>) -> bool {
+    let Some(TokenTree::Group(args)) = attribute.next() else {
+        panic!("Expected `#[component(storage = \"...\")]`");
+    };
+    let args: Vec<String> = args.stream().into_iter().map(|t| t.to_string()).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["storage", "=", "\"SparseSet\""] => true,
+        ["storage", "=", "\"Table\""] => false,
+        _ => panic!(
+            "Expected `storage = \"Table\"` or `storage = \"SparseSet\"` in `#[component(...)]`"
+        ),
+    }
+}
+
+/// Derives `SystemParam` for a struct whose fields are all `SystemParam`s, so a group of
+/// parameters can be used in a system as a single parameter. The struct may have one lifetime,
+/// which is used for the borrows in its fields:
+///
+/// ```rs
+/// #[derive(SystemParam)]
+/// struct Combat<'a> {
+///     enemies: &'a Query<&'static Health>,
+///     cmds: &'a mut CommandQueue,
+/// }
+/// ```
+///
+/// The struct's data is a tuple of each field's `SystemParam::Data`, so it can have up to 12
+/// fields.
+#[proc_macro_derive(SystemParam)]
+pub fn system_param_derive(input: TokenStream) -> TokenStream {
+    let mut source = input.into_iter().peekable();
+
+    // Skip over attributes and visibility
+    while let Some(token) = source.peek() {
+        match token {
+            TokenTree::Punct(_) => source.next(),
+            TokenTree::Group(_) => source.next(),
+            TokenTree::Ident(ident) => match ident.to_string().as_str() {
+                "pub" => source.next(),
+                "struct" => source.next(),
+                _ => break,
+            },
+            _ => unreachable!("Unexpected token while deriving SystemParam"),
+        };
+    }
+
+    // Get the struct's name
+    let struct_name = source.next().unwrap().to_string();
+
+    // Get the struct's lifetime, if it has one
+    let mut lifetime = None;
+    if matches!(source.peek(), Some(TokenTree::Punct(punct)) if punct.as_char() == '<') {
+        source.next();
+        for token in source.by_ref() {
+            match token {
+                TokenTree::Punct(punct) if punct.as_char() == '>' => break,
+                TokenTree::Ident(ident) if lifetime.is_none() => {
+                    lifetime = Some(ident.to_string())
+                }
+                TokenTree::Punct(punct) if punct.as_char() == '\'' => {}
+                _ => panic!("SystemParam can only be derived for structs with at most one lifetime, and no generics"),
+            }
+        }
+    }
+
+    let Some(TokenTree::Group(body)) = source.next() else {
+        panic!("SystemParam can only be derived for structs with named fields");
+    };
+    let fields = parse_fields(body.stream());
+    if fields.is_empty() || fields.len() > 12 {
+        panic!("SystemParam can only be derived for structs with 1 to 12 fields");
+    }
+
+    let (impl_generics, struct_ty, fetch_ty) = match &lifetime {
+        Some(lifetime) => (
+            format!("<'{lifetime}>"),
+            format!("{struct_name}<'{lifetime}>"),
+            format!("{struct_name}<'__secs_fetch>"),
+        ),
+        None => (String::new(), struct_name.clone(), struct_name.clone()),
+    };
+
+    let mut data = String::new();
+    let mut fetches = String::new();
+    let mut accesses = String::new();
+    let mut needs_world = String::from("false");
+    for (idx, (name, ty)) in fields.iter().enumerate() {
+        let ty = type_string(ty, lifetime.as_deref());
+        let param = format!("<{ty} as secs::system::SystemParam>");
+        data += &format!("{param}::Data, ");
+        fetches += &format!("{name}: {param}::fetch(&mut data.{idx}, world), ");
+        needs_world += &format!(" || {param}::NEEDS_WORLD");
+        accesses += &format!("{param}::access(access);\n");
+    }
+
+    TokenStream::from_str(&format!(
+        "
+        impl{impl_generics} secs::system::SystemParam for {struct_ty} {{
+            type Data = ({data});
+            type Fetch<'__secs_fetch> = {fetch_ty};
+            const NEEDS_WORLD: bool = {needs_world};
+
+            fn fetch<'__secs_fetch>(
+                data: &'__secs_fetch mut Self::Data,
+                world: &mut Option<&'__secs_fetch mut secs::world::World>,
+            ) -> Self::Fetch<'__secs_fetch> {{
+                {struct_name} {{ {fetches} }}
+            }}
+
+            fn access(access: &mut secs::system::access::Access) {{
+                {accesses}
+            }}
+        }}
+        "
+    ))
+    .unwrap()
+}
+
+/// Parses the named fields in a struct's body into `(name, type)` pairs.
+fn parse_fields(body: TokenStream) -> Vec<(String, Vec<TokenTree>)> {
+    let mut fields = Vec::new();
+    let mut source = body.into_iter().peekable();
+
+    while source.peek().is_some() {
+        // Skip over attributes and visibility
+        while let Some(token) = source.peek() {
+            match token {
+                TokenTree::Punct(punct) if punct.as_char() == '#' => source.next(),
+                TokenTree::Group(_) => source.next(),
+                TokenTree::Ident(ident) if ident.to_string() == "pub" => source.next(),
+                _ => break,
+            };
+        }
+
+        let name = source.next().unwrap().to_string();
+        // Skip the `:`
+        source.next();
+
+        // Types may contain commas inside angle brackets, so only stop at a comma that isn't
+        // inside a generic. The `>` in a `->` (like `fn() -> u32`) doesn't close a generic.
+        let mut ty: Vec<TokenTree> = Vec::new();
+        let mut depth = 0;
+        for token in source.by_ref() {
+            if let TokenTree::Punct(punct) = &token {
+                let after_dash =
+                    matches!(ty.last(), Some(TokenTree::Punct(last)) if last.as_char() == '-');
+                match punct.as_char() {
+                    '<' => depth += 1,
+                    '>' if !after_dash => depth -= 1,
+                    ',' if depth == 0 => break,
+                    _ => {}
+                }
+            }
+            ty.push(token);
+        }
+
+        fields.push((name, ty));
+    }
+
+    fields
+}
+
+/// Converts a field's type back into a string, replacing the struct's lifetime with `'static`.
+/// `SystemParam::Data` has to be `'static`, so this is the type used to get the field's data.
+fn type_string(tokens: &[TokenTree], lifetime: Option<&str>) -> String {
+    let mut result = String::new();
+    let mut tokens = tokens.iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == '\'' => {
+                let name = tokens.next().unwrap().to_string();
+                if Some(name.as_str()) == lifetime {
+                    result += "'static ";
+                } else {
+                    result += &format!("'{name} ");
+                }
+            }
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::None => ("", ""),
+                };
+                let inner: Vec<TokenTree> = group.stream().into_iter().collect();
+                result += &format!("{open}{}{close} ", type_string(&inner, lifetime));
+            }
+            TokenTree::Punct(punct) if punct.spacing() == Spacing::Joint => {
+                result.push(punct.as_char());
+            }
+            token => result += &format!("{token} "),
+        }
+    }
+
+    result
+}
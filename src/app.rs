@@ -0,0 +1,245 @@
+//! An `App` wraps a `World`, and lets features be added to it as `Plugin`s.
+//!
+//! Plugins are self-contained chunks of setup code - they add the systems, resources, and other
+//! plugins a feature needs. This lets crates ship a feature as a single plugin, instead of asking
+//! users to make a series of `insert_resource` and `add_system` calls.
+
+use {
+    crate::{
+        _crate_prelude::*,
+        state::{States, TransitionSystems},
+        sync::{AnyData, MaybeSend, MaybeSync},
+        system::{diagnostics::SystemHook, error::SystemOutput, IntoSystem, System},
+        time::Clock,
+    },
+    core::any::type_name,
+};
+
+/// A chunk of setup code for an `App`. See the module docs.
+pub trait Plugin: 'static {
+    /// Sets up the plugin's systems, resources, etc in the app.
+    fn build(&self, app: &mut App);
+
+    /// The plugin's name, for error messages. By default, this is the plugin's type name.
+    fn name(&self) -> &str {
+        type_name::<Self>()
+    }
+    /// If this returns true, the plugin can only be added to an app once. Defaults to true.
+    fn is_unique(&self) -> bool {
+        true
+    }
+    /// Plugins that have to be added to an app before this plugin can be added.
+    fn dependencies(&self) -> Vec<PluginId> {
+        Vec::new()
+    }
+}
+/// Any function that takes an `App` can be used as a plugin.
+impl<F: Fn(&mut App) + 'static> Plugin for F {
+    fn build(&self, app: &mut App) {
+        self(app)
+    }
+}
+
+/// Identifies a type of `Plugin`.
+#[derive(Clone, Copy, Debug)]
+pub struct PluginId {
+    /// The plugin's `TypeId`.
+    pub id: TypeId,
+    /// The plugin's type name, for error messages.
+    pub name: &'static str,
+}
+impl PluginId {
+    /// Get the ID for a type of plugin.
+    pub fn of<P: Plugin>() -> Self {
+        Self {
+            id: TypeId::of::<P>(),
+            name: type_name::<P>(),
+        }
+    }
+}
+
+/// A `World`, and the `Plugin`s that have been added to it.
+#[derive(Default)]
+pub struct App {
+    /// The World the app's plugins set up.
+    pub world: World,
+    /// Every plugin that's been added to the app.
+    pub plugins: Vec<PluginId>,
+}
+impl App {
+    /// Makes an app with an empty `World`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a plugin to the app, then builds it.
+    ///
+    /// Panics if the plugin is unique and was already added, or if one of its dependencies
+    /// hasn't been added yet.
+    pub fn add_plugin<P: Plugin>(&mut self, plugin: P) -> &mut Self {
+        let id = PluginId::of::<P>();
+        if plugin.is_unique() && self.is_plugin_added::<P>() {
+            panic!(
+                "Plugin `{}` was added to the app twice, but it's unique",
+                plugin.name()
+            );
+        }
+        for dependency in plugin.dependencies() {
+            if !self.plugins.iter().any(|added| added.id == dependency.id) {
+                panic!(
+                    "Plugin `{}` depends on plugin `{}`, which hasn't been added to the app",
+                    plugin.name(),
+                    dependency.name
+                );
+            }
+        }
+
+        self.plugins.push(id);
+        plugin.build(self);
+        self
+    }
+    /// Checks if a plugin has been added to the app.
+    pub fn is_plugin_added<P: Plugin>(&self) -> bool {
+        self.plugins
+            .iter()
+            .any(|added| added.id == TypeId::of::<P>())
+    }
+
+    /// Registers a system in the app's world. See `World::add_system`.
+    pub fn add_system<S: System<In = ()> + MaybeSend + MaybeSync + 'static>(
+        &mut self,
+        system: impl IntoSystem<S>,
+    ) -> &mut Self
+    where
+        S::Out: SystemOutput,
+    {
+        self.world.add_system(system);
+        self
+    }
+    /// Registers a fixed-timestep system in the app's world. See `World::add_fixed_system`.
+    pub fn add_fixed_system<S: System<In = ()> + MaybeSend + MaybeSync + 'static>(
+        &mut self,
+        system: impl IntoSystem<S>,
+    ) -> &mut Self
+    where
+        S::Out: SystemOutput,
+    {
+        self.world.add_fixed_system(system);
+        self
+    }
+    /// Adds a state machine to the app's world. See `World::add_state`.
+    pub fn add_state<S: States>(&mut self, initial: S) -> &mut Self {
+        self.world.add_state(initial);
+        self
+    }
+    /// Registers a system that runs when a state is entered or exited. See
+    /// `World::add_state_system`.
+    pub fn add_state_system<
+        G: TransitionSystems,
+        S: System<In = ()> + MaybeSend + MaybeSync + 'static,
+    >(
+        &mut self,
+        group: G,
+        system: impl IntoSystem<S>,
+    ) -> &mut Self
+    where
+        S::Out: SystemOutput,
+    {
+        self.world.add_state_system(group, system);
+        self
+    }
+    /// Sets the clock the app's world reads the time from. See `World::set_clock`.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) -> &mut Self {
+        self.world.set_clock(clock);
+        self
+    }
+    /// Adds a hook that's called before and after every system. See `World::add_system_hook`.
+    pub fn add_system_hook(&mut self, hook: impl SystemHook + 'static) -> &mut Self {
+        self.world.add_system_hook(hook);
+        self
+    }
+    /// Inserts a resource into the app's world. See `World::insert_resource`.
+    pub fn insert_resource(&mut self, resource: impl Any + MaybeSend + MaybeSync) -> &mut Self {
+        self.world.insert_resource(resource);
+        self
+    }
+
+    /// Runs the app's systems once. See `World::run_once`.
+    pub fn run_once(&mut self) {
+        self.world.run_once();
+    }
+    /// Runs the app's systems in a loop, until the `ExitRunLoop` command is used. See
+    /// `World::run`.
+    pub fn run(&mut self) -> Option<Box<AnyData>> {
+        self.world.run()
+    }
+    /// Runs the app's systems at most `runs` times. See `World::run_for`.
+    pub fn run_for(&mut self, runs: usize) -> Option<Box<AnyData>> {
+        self.world.run_for(runs)
+    }
+    /// Runs the app's systems in a loop until `done` returns true. See `World::run_until`.
+    pub fn run_until(&mut self, done: impl FnMut(&World) -> bool) -> Option<Box<AnyData>> {
+        self.world.run_until(done)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{self as secs, prelude::*},
+        alloc::vec,
+    };
+
+    #[derive(Component)]
+    struct Enemy;
+
+    struct EnemyPlugin;
+    impl Plugin for EnemyPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(0_usize).add_system(count_enemies);
+        }
+    }
+    fn count_enemies(query: &Query<&Enemy>, count: &mut Resource<usize>) {
+        *count.get_mut() = query.len();
+    }
+
+    struct SpawnerPlugin;
+    impl Plugin for SpawnerPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_system(|cmds: &mut CommandQueue| cmds.spawn(Enemy));
+        }
+        fn dependencies(&self) -> Vec<PluginId> {
+            vec![PluginId::of::<EnemyPlugin>()]
+        }
+    }
+
+    /// Verifies that plugins build in the order they're added.
+    #[test]
+    fn plugins() {
+        let mut app = App::new();
+        app.world.spawn(Enemy);
+        app.add_plugin(EnemyPlugin)
+            .add_plugin(SpawnerPlugin)
+            .add_plugin(|app: &mut App| {
+                app.add_system(|cmds: &mut CommandQueue| cmds.exit_run_loop());
+            });
+        app.run();
+
+        assert!(app.is_plugin_added::<EnemyPlugin>());
+        assert_eq!(*app.world.get_resource::<usize>().unwrap(), 1);
+        assert_eq!(app.world.storage.num_entities, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "added to the app twice")]
+    fn unique_plugins() {
+        App::new().add_plugin(EnemyPlugin).add_plugin(EnemyPlugin);
+    }
+
+    #[test]
+    #[should_panic(expected = "which hasn't been added")]
+    fn plugin_dependencies() {
+        App::new().add_plugin(SpawnerPlugin);
+    }
+}
@@ -1,8 +1,12 @@
-use crate::_crate_prelude::*;
+use crate::{
+    _crate_prelude::*,
+    sync::{MaybeSend, MaybeSync},
+    world::storage::EntityLocation,
+};
 
 /// Methods that make handling Components easier. These are automatically implemented with
-/// `#[derive(Component)]`.
-pub trait Component: AsAny {
+/// `#[derive(Component)]`. With the `parallel` feature, components have to be `Send` and `Sync`.
+pub trait Component: AsAny + MaybeSend + MaybeSync {
     /// Prepares the `World`'s storage to store this `Component`.
     ///
     /// Why is this necessary? It's because there's no way to access
@@ -12,6 +16,29 @@ pub trait Component: AsAny {
     /// handle this solves the issue, since the impl has direct access
     /// to the component's type.
     fn prep_storage(&self, storage: &mut Storage);
+
+    /// How this component is stored. By default, components are stored in tables; this can be
+    /// changed with `#[component(storage = "SparseSet")]`. See `StorageType`.
+    fn storage_type() -> StorageType
+    where
+        Self: Sized,
+    {
+        StorageType::Table
+    }
+}
+
+/// The ways a component can be stored in the World. See the `storage` module.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StorageType {
+    /// The component is stored in the entity's table. Queries can iterate over tables quickly,
+    /// but adding or removing the component moves the entity to another table. This is the
+    /// default.
+    #[default]
+    Table,
+    /// The component is stored in a sparse set, outside of the entity's table. Adding and
+    /// removing the component is fast, but queries are slower. This is meant for components
+    /// that are added and removed often, like markers. See the `sparse_set` module.
+    SparseSet,
 }
 
 /// An immutable or mutable borrow of a component.
@@ -19,21 +46,38 @@ pub trait ComponentBorrow {
     /// The component that's being borrowed: The `C` in `&C` or `&mut C`.
     type Component: Component;
 }
+#[allow(clippy::needless_lifetimes)]
 impl<'a, C: Component> ComponentBorrow for &'a C {
     type Component = C;
 }
+#[allow(clippy::needless_lifetimes)]
 impl<'a, C: Component> ComponentBorrow for &'a mut C {
     type Component = C;
 }
 
 /// A tuple of components. Typically used to spawn entities.
-pub trait Bundle {
+///
+/// When the bundle's type is known, like in `World::spawn`, its components are written straight
+/// into the storage with `prep_storage` and `write`. Boxed bundles, like the ones in commands,
+/// can't do that, so they're split into boxed components with `components_from_box` instead.
+pub trait Bundle: MaybeSend + MaybeSync + 'static {
     /// Consumes the bundle, returning a vec of the components it contained.
     fn components(self) -> Vec<Box<dyn Component>>;
     /// Same as above, but for a boxed bundle. Unsized types can't be moved,
     /// so in the case of a `Box<dyn Bundle>`, we need a special method that
     /// doesn't move out of the box.
     fn components_from_box(self: Box<Self>) -> Vec<Box<dyn Component>>;
+
+    /// Prepares the storage to store every component in the bundle, and adds the `TypeId`s of
+    /// the ones that are stored in tables to `types`. See `Storage::insert_bundle`.
+    fn prep_storage(storage: &mut Storage, types: &mut Vec<TypeId>)
+    where
+        Self: Sized;
+    /// Writes every component in the bundle into the storage, for an entity that's already been
+    /// moved to `location`. See `Storage::write_component`.
+    fn write(self, entity: usize, location: EntityLocation, storage: &mut Storage)
+    where
+        Self: Sized;
 }
 
 impl<C: Component> Bundle for C {
@@ -44,6 +88,17 @@ impl<C: Component> Bundle for C {
     fn components_from_box(self: Box<Self>) -> Vec<Box<dyn Component>> {
         vec![self]
     }
+
+    fn prep_storage(storage: &mut Storage, types: &mut Vec<TypeId>) {
+        storage.prep_for::<C>();
+        if C::storage_type() == StorageType::Table {
+            types.push(TypeId::of::<C>());
+        }
+    }
+
+    fn write(self, entity: usize, location: EntityLocation, storage: &mut Storage) {
+        storage.write_component(entity, location, self);
+    }
 }
 macro_rules! bundle_impl {
     ($_: ident) => {};
@@ -9,8 +9,12 @@
 //!
 //! The code is laid out in a very specific way in SECS. Here's the current list of modules, and
 //! what you can expect to find in them.
+//! - `app`: Defines the `App` and `Plugin`s, which make setting up a `World` easier.
 //! - `entity`: Defines entities, components, and bundles in SECS.
+//! - `state`: Defines state machines, and the systems that run when states change.
+//! - `sync`: Defines the locks components and resources are stored in, for the `parallel` feature.
 //! - `world`: Defines SECS' world, and the archetypes that store components.
+//! - `time`: Defines the World's clock and time, and fixed-timestep systems.
 //! - `system`: Defines systems, and basically the whole system API - queries, resources, etc...
 //!
 //! It's also worth noting that SECS does *not* use `mod.rs`. For example, the world module has a
@@ -20,18 +24,21 @@
 
 #![no_std]
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
+pub mod app;
 pub mod entity;
+pub mod state;
+pub mod sync;
 pub mod system;
+pub mod time;
 pub mod world;
 
 pub(crate) mod _crate_prelude {
     pub use super::{
         entity::Component,
-        world::{
-            storage::{Archetype, Storage},
-            World,
-        },
+        world::{storage::Storage, World},
         AsAny,
     };
 
@@ -41,15 +48,21 @@ pub(crate) mod _crate_prelude {
 
 pub mod prelude {
     pub use crate::{
+        app::{App, Plugin, PluginId},
         entity::Component,
+        state::{in_state, NextState, OnEnter, OnExit, State, States},
         system::{
-            command::{Command, CommandQueue},
+            command::{apply_deferred, Command, CommandMode, CommandQueue},
+            local::Local,
+            param_set::ParamSet,
             query::Query,
             resource::Resource,
+            In, IntoSystem,
         },
+        time::{Clock, Time},
         world::World,
     };
-    pub use secs_macros::Component;
+    pub use secs_macros::{Component, SystemParam};
 }
 
 use _crate_prelude::*;
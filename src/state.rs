@@ -0,0 +1,304 @@
+//! Finite state machines, for things like a game's menus.
+//!
+//! A state is any type implementing `States` - usually an enum, like `Menu`, `Playing`, and
+//! `Paused`. `World::add_state` stores the current state in the `State<S>` resource. Systems can
+//! read it, but don't change it directly; instead, they set the `NextState<S>` resource. The
+//! transition is applied at the start of the World's next frame, before the fixed systems run,
+//! so every system in a frame sees the same state.
+//!
+//! When a transition happens, the systems added to `OnExit(old_state)` run, then the state
+//! changes, then the systems added to `OnEnter(new_state)` run. The `OnEnter` systems for the
+//! initial state run the first time the World's systems run. Systems that should only run in
+//! some states can use the `in_state` run condition:
+//!
+//! ```ignore
+//! world.add_system(move_player.run_if(in_state(GameState::Playing)));
+//! ```
+
+use {
+    crate::{
+        _crate_prelude::*,
+        sync::{MaybeSend, MaybeSync},
+        system::{resource::Resource, Systems},
+    },
+    core::mem,
+};
+
+/// Types that can be used as states. This is implemented for any type that can be cloned and
+/// compared (and sent between threads, with the `parallel` feature).
+pub trait States: Clone + PartialEq + MaybeSend + MaybeSync + 'static {}
+impl<T: Clone + PartialEq + MaybeSend + MaybeSync + 'static> States for T {}
+
+/// A resource storing the current state. This can't be changed directly - use `NextState`
+/// instead, so `OnExit` and `OnEnter` systems run.
+#[derive(Debug)]
+pub struct State<S: States>(S);
+impl<S: States> State<S> {
+    /// The current state.
+    pub fn get(&self) -> &S {
+        &self.0
+    }
+}
+
+/// A resource storing the state to transition to. If this is set, the transition happens the
+/// next time the World's systems run.
+#[derive(Debug)]
+pub struct NextState<S: States>(pub Option<S>);
+impl<S: States> NextState<S> {
+    /// Queue a transition to `state`. This overwrites any transition that was already queued.
+    pub fn set(&mut self, state: S) {
+        self.0 = Some(state);
+    }
+}
+
+/// The systems that run when a state is entered. Used with `World::add_state_system`.
+pub struct OnEnter<S: States>(pub S);
+/// The systems that run when a state is exited. Used with `World::add_state_system`.
+pub struct OnExit<S: States>(pub S);
+
+/// A group of systems that runs during state transitions - either `OnEnter` or `OnExit`.
+pub trait TransitionSystems {
+    /// The state type this group is for.
+    type State: States;
+
+    /// Gets this group's systems, creating the group if it doesn't exist yet.
+    fn systems(self, groups: &mut StateSystems<Self::State>) -> &mut Systems;
+}
+impl<S: States> TransitionSystems for OnEnter<S> {
+    type State = S;
+
+    fn systems(self, groups: &mut StateSystems<S>) -> &mut Systems {
+        StateSystems::group(&mut groups.on_enter, self.0)
+    }
+}
+impl<S: States> TransitionSystems for OnExit<S> {
+    type State = S;
+
+    fn systems(self, groups: &mut StateSystems<S>) -> &mut Systems {
+        StateSystems::group(&mut groups.on_exit, self.0)
+    }
+}
+
+/// A resource storing the `OnEnter` and `OnExit` systems for every value of a state.
+pub struct StateSystems<S: States> {
+    /// The systems to run when each state is entered.
+    pub on_enter: Vec<(S, Systems)>,
+    /// The systems to run when each state is exited.
+    pub on_exit: Vec<(S, Systems)>,
+    /// If the `OnEnter` systems for the initial state have run yet.
+    pub entered_initial: bool,
+}
+impl<S: States> Default for StateSystems<S> {
+    fn default() -> Self {
+        Self {
+            on_enter: Vec::new(),
+            on_exit: Vec::new(),
+            entered_initial: false,
+        }
+    }
+}
+impl<S: States> StateSystems<S> {
+    fn group(groups: &mut Vec<(S, Systems)>, state: S) -> &mut Systems {
+        let idx = match groups.iter().position(|(group, _)| *group == state) {
+            Some(idx) => idx,
+            None => {
+                groups.push((state, Systems::default()));
+                groups.len() - 1
+            }
+        };
+        &mut groups[idx].1
+    }
+
+    /// Runs the groups for `state`. There's usually only one, but systems added while a
+    /// transition runs end up in a separate group (see `apply_state_transition`).
+    fn run(groups: &mut [(S, Systems)], state: &S, world: &mut World) {
+        for (_, systems) in groups.iter_mut().filter(|(group, _)| group == state) {
+            systems.run(world);
+        }
+    }
+
+    /// Puts groups that were taken out with `mem::take` back, keeping any that were added
+    /// while they were out.
+    fn restore(groups: &mut Vec<(S, Systems)>, mut taken: Vec<(S, Systems)>) {
+        taken.append(groups);
+        *groups = taken;
+    }
+}
+
+/// Applies a pending transition of the state `S`, running its `OnExit` and `OnEnter` systems.
+/// `World::add_state` adds this to the World's `Systems::state_transitions`, which the World
+/// applies at the start of each frame. Transitioning to the state that's already active does nothing.
+///
+/// The `OnEnter` and `OnExit` systems are taken out of the `StateSystems` resource while they
+/// run, so they can use it themselves - for example, to add more state systems.
+pub fn apply_state_transition<S: States>(world: &mut World) {
+    let Some(groups) = world.storage.get_resource(TypeId::of::<StateSystems<S>>()) else {
+        return;
+    };
+    let (mut on_enter, mut on_exit, entered_initial) = {
+        let mut groups = groups.borrow_mut();
+        let groups: &mut StateSystems<S> = groups.downcast_mut().unwrap();
+        (
+            mem::take(&mut groups.on_enter),
+            mem::take(&mut groups.on_exit),
+            mem::replace(&mut groups.entered_initial, true),
+        )
+    };
+
+    if !entered_initial {
+        let current = world.get_resource::<State<S>>().unwrap().0.clone();
+        StateSystems::run(&mut on_enter, &current, world);
+    }
+
+    let next = world
+        .get_resource_mut::<NextState<S>>()
+        .and_then(|mut next| next.0.take());
+    if let Some(next) = next {
+        let current = world.get_resource::<State<S>>().unwrap().0.clone();
+        if next != current {
+            StateSystems::run(&mut on_exit, &current, world);
+            world.get_resource_mut::<State<S>>().unwrap().0 = next.clone();
+            StateSystems::run(&mut on_enter, &next, world);
+        }
+    }
+
+    let mut groups = groups.borrow_mut();
+    let groups: &mut StateSystems<S> = groups.downcast_mut().unwrap();
+    StateSystems::restore(&mut groups.on_enter, on_enter);
+    StateSystems::restore(&mut groups.on_exit, on_exit);
+}
+
+/// A run condition that's `true` while the World is in `state`. See `IntoSystem::run_if`.
+pub fn in_state<S: States>(state: S) -> impl Fn(&Resource<State<S>>) -> bool + 'static {
+    move |current: &Resource<State<S>>| *current.get().get() == state
+}
+
+/// Initial setup for a state, used by `World::add_state`. If the state was already added, its
+/// state systems are kept, and the `OnEnter` systems for the new initial state run next frame.
+pub(crate) fn init_state<S: States>(world: &mut World, initial: S) {
+    world.insert_resource(State(initial));
+    world.insert_resource(NextState::<S>(None));
+    if let Some(mut groups) = world.get_resource_mut::<StateSystems<S>>() {
+        groups.entered_initial = false;
+        return;
+    }
+    world.insert_resource(StateSystems::<S>::default());
+    world
+        .systems
+        .borrow_mut()
+        .state_transitions
+        .push(apply_state_transition::<S>);
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{prelude::*, time::tests::FakeClock},
+        core::time::Duration,
+    };
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum GameState {
+        Menu,
+        Playing,
+    }
+
+    #[derive(Default)]
+    struct Log(alloc::vec::Vec<&'static str>);
+
+    fn enter_menu(log: &mut Resource<Log>) {
+        log.get_mut().0.push("enter menu");
+    }
+    fn exit_menu(log: &mut Resource<Log>) {
+        log.get_mut().0.push("exit menu");
+    }
+    fn enter_playing(log: &mut Resource<Log>) {
+        log.get_mut().0.push("enter playing");
+    }
+    fn play(log: &mut Resource<Log>) {
+        log.get_mut().0.push("play");
+    }
+    fn fixed_play(log: &mut Resource<Log>) {
+        log.get_mut().0.push("fixed play");
+    }
+    fn start_game(next: &mut Resource<NextState<GameState>>) {
+        next.get_mut().set(GameState::Playing);
+    }
+
+    /// Verifies that transitions run `OnExit` and `OnEnter` systems, and that `in_state` only
+    /// runs systems in the right state.
+    #[test]
+    fn transitions() {
+        let mut world = World::default();
+        world.insert_resource(Log::default());
+        world.add_state(GameState::Menu);
+        world.add_state_system(OnEnter(GameState::Menu), enter_menu);
+        world.add_state_system(OnExit(GameState::Menu), exit_menu);
+        world.add_state_system(OnEnter(GameState::Playing), enter_playing);
+        world.add_system(play.run_if(in_state(GameState::Playing)));
+
+        world.run_once();
+        assert_eq!(world.get_resource::<Log>().unwrap().0, ["enter menu"]);
+
+        world.run_system_once(start_game);
+        // Nothing changes until the systems run again
+        assert_eq!(
+            world.get_resource::<State<GameState>>().unwrap().get(),
+            &GameState::Menu
+        );
+
+        world.run_once();
+        assert_eq!(
+            world.get_resource::<State<GameState>>().unwrap().get(),
+            &GameState::Playing
+        );
+        assert_eq!(
+            world.get_resource::<Log>().unwrap().0,
+            ["enter menu", "exit menu", "enter playing", "play"]
+        );
+    }
+
+    /// Verifies that fixed systems see a transition from the frame it's applied in.
+    #[test]
+    fn fixed_systems_see_transitions() {
+        let mut world = World::default();
+        world.insert_resource(Log::default());
+        let clock = FakeClock::default();
+        world.set_clock(clock.clone());
+        world.get_resource_mut::<Time>().unwrap().fixed_step = Duration::from_millis(10);
+        world.add_state(GameState::Menu);
+        world.add_state_system(OnEnter(GameState::Playing), enter_playing);
+        world.add_fixed_system(fixed_play.run_if(in_state(GameState::Playing)));
+        world.add_system(play.run_if(in_state(GameState::Playing)));
+
+        clock.advance(10);
+        world.run_once();
+        assert!(world.get_resource::<Log>().unwrap().0.is_empty());
+
+        world.run_system_once(start_game);
+        clock.advance(10);
+        world.run_once();
+        assert_eq!(
+            world.get_resource::<Log>().unwrap().0,
+            ["enter playing", "fixed play", "play"]
+        );
+    }
+
+    /// Verifies that adding a state again runs the `OnEnter` systems for its new initial state.
+    #[test]
+    fn re_add_state() {
+        let mut world = World::default();
+        world.insert_resource(Log::default());
+        world.add_state(GameState::Menu);
+        world.add_state_system(OnEnter(GameState::Menu), enter_menu);
+        world.add_state_system(OnEnter(GameState::Playing), enter_playing);
+        world.run_once();
+
+        world.add_state(GameState::Playing);
+        world.run_once();
+        assert_eq!(
+            world.get_resource::<Log>().unwrap().0,
+            ["enter menu", "enter playing"]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! The pointer and lock types the World stores its data in.
+//!
+//! By default, SECS is single-threaded, and stores components and resources in
+//! `Rc<RefCell<...>>`s. With the `parallel` feature, systems can run on multiple threads (see
+//! `system::executor`), so the World's data has to be thread-safe instead. This module swaps
+//! between the two: `Shared` is either an `Rc` or an `Arc`, and `Lock` is either a `RefCell` or
+//! an `RwLock`. Everything else in SECS uses these types, so the rest of the code doesn't need to
+//! care which one it's using.
+//!
+//! Components aren't locked one at a time. Each component column (see `world::storage::table`)
+//! is a `Vec` of components in a single `Lock`, and `downcast_index` and `downcast_index_mut`
+//! borrow one component out of it. Borrowing a component borrows its whole column.
+//!
+//! Like a `RefCell`, borrowing a `Lock` that's already mutably borrowed panics, even with the
+//! `parallel` feature - it never blocks. The executor only runs systems at the same time if their
+//! data doesn't conflict, so blocking would only ever hide a bug.
+//!
+//! `MaybeSend` and `MaybeSync` are `Send` and `Sync` with the `parallel` feature, and are
+//! implemented for every type otherwise. Components, resources, and systems need to implement
+//! them.
+
+#[cfg(not(feature = "parallel"))]
+mod single_threaded {
+    pub use {
+        alloc::rc::Rc as Shared,
+        core::cell::{Ref, RefCell as Lock, RefMut},
+    };
+    use {alloc::vec::Vec, core::any::Any};
+
+    /// A type-erased value stored in a `Lock`, like a resource.
+    pub type AnyData = dyn Any;
+
+    /// `Send` with the `parallel` feature. Without it, this is implemented for every type.
+    pub trait MaybeSend {}
+    impl<T: ?Sized> MaybeSend for T {}
+    /// `Sync` with the `parallel` feature. Without it, this is implemented for every type.
+    pub trait MaybeSync {}
+    impl<T: ?Sized> MaybeSync for T {}
+
+    /// Immutably borrows type-erased data as a `T`.
+    pub fn downcast_ref<T: 'static>(data: &Lock<AnyData>) -> Ref<'_, T> {
+        Ref::map(data.borrow(), |data| data.downcast_ref().unwrap())
+    }
+    /// Mutably borrows type-erased data as a `T`.
+    pub fn downcast_mut<T: 'static>(data: &Lock<AnyData>) -> RefMut<'_, T> {
+        RefMut::map(data.borrow_mut(), |data| data.downcast_mut().unwrap())
+    }
+    /// Immutably borrows the element at `idx` of type-erased data that's a `Vec<T>`. The whole
+    /// `Vec` stays borrowed until the `Ref` is dropped.
+    pub fn downcast_index<T: 'static>(data: &Lock<AnyData>, idx: usize) -> Ref<'_, T> {
+        Ref::map(data.borrow(), |data| {
+            &data.downcast_ref::<Vec<T>>().unwrap()[idx]
+        })
+    }
+    /// Mutably borrows the element at `idx` of type-erased data that's a `Vec<T>`. The whole
+    /// `Vec` stays borrowed until the `RefMut` is dropped.
+    pub fn downcast_index_mut<T: 'static>(data: &Lock<AnyData>, idx: usize) -> RefMut<'_, T> {
+        RefMut::map(data.borrow_mut(), |data| {
+            &mut data.downcast_mut::<Vec<T>>().unwrap()[idx]
+        })
+    }
+}
+#[cfg(not(feature = "parallel"))]
+pub use single_threaded::*;
+
+#[cfg(feature = "parallel")]
+mod multi_threaded {
+    pub use alloc::sync::Arc as Shared;
+    use {
+        alloc::vec::Vec,
+        core::{
+            any::Any,
+            fmt::{self, Debug, Display, Formatter},
+            marker::PhantomData,
+            ops::{Deref, DerefMut},
+        },
+        std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError},
+    };
+
+    /// A type-erased value stored in a `Lock`, like a resource.
+    pub type AnyData = dyn Any + Send + Sync;
+
+    /// `Send` with the `parallel` feature. Without it, this is implemented for every type.
+    pub trait MaybeSend: Send {}
+    impl<T: ?Sized + Send> MaybeSend for T {}
+    /// `Sync` with the `parallel` feature. Without it, this is implemented for every type.
+    pub trait MaybeSync: Sync {}
+    impl<T: ?Sized + Sync> MaybeSync for T {}
+
+    /// An `RwLock` with the same API as a `RefCell`. Borrowing it panics instead of blocking if
+    /// it's already mutably borrowed.
+    #[derive(Debug, Default)]
+    pub struct Lock<T: ?Sized>(RwLock<T>);
+    impl<T> Lock<T> {
+        pub fn new(value: T) -> Self {
+            Self(RwLock::new(value))
+        }
+    }
+    impl<T: ?Sized> Lock<T> {
+        /// Immutably borrows the value. Panics if it's mutably borrowed.
+        pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+            match self.0.try_read() {
+                Ok(guard) => guard,
+                Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+                Err(TryLockError::WouldBlock) => panic!("already mutably borrowed"),
+            }
+        }
+        /// Mutably borrows the value. Panics if it's already borrowed.
+        pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, T> {
+            match self.0.try_write() {
+                Ok(guard) => guard,
+                Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+                Err(TryLockError::WouldBlock) => panic!("already borrowed"),
+            }
+        }
+    }
+
+    /// An immutable borrow of type-erased data, as a `T`. `RwLock` guards can't be mapped to
+    /// another type on stable Rust, so this downcasts the data every time it's dereferenced.
+    ///
+    /// If `idx` is set, the data is a `Vec<T>`, and this borrows the element at `idx`.
+    pub struct Ref<'a, T> {
+        guard: RwLockReadGuard<'a, AnyData>,
+        idx: Option<usize>,
+        _ty: PhantomData<&'a T>,
+    }
+    impl<T: 'static> Deref for Ref<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            match self.idx {
+                None => self.guard.downcast_ref().unwrap(),
+                Some(idx) => &self.guard.downcast_ref::<Vec<T>>().unwrap()[idx],
+            }
+        }
+    }
+    /// A mutable borrow of type-erased data, as a `T`. See `Ref`.
+    pub struct RefMut<'a, T> {
+        guard: RwLockWriteGuard<'a, AnyData>,
+        idx: Option<usize>,
+        _ty: PhantomData<&'a mut T>,
+    }
+    impl<T: 'static> Deref for RefMut<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            match self.idx {
+                None => self.guard.downcast_ref().unwrap(),
+                Some(idx) => &self.guard.downcast_ref::<Vec<T>>().unwrap()[idx],
+            }
+        }
+    }
+    impl<T: 'static> DerefMut for RefMut<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            match self.idx {
+                None => self.guard.downcast_mut().unwrap(),
+                Some(idx) => &mut self.guard.downcast_mut::<Vec<T>>().unwrap()[idx],
+            }
+        }
+    }
+
+    // Like `core::cell::Ref`, borrows format as the value they borrow
+    macro_rules! impl_fmt {
+        ($($trait:ident),*) => {$(
+            impl<T: $trait + 'static> $trait for Ref<'_, T> {
+                fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                    (**self).fmt(f)
+                }
+            }
+            impl<T: $trait + 'static> $trait for RefMut<'_, T> {
+                fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                    (**self).fmt(f)
+                }
+            }
+        )*};
+    }
+    impl_fmt!(Debug, Display);
+
+    /// Immutably borrows type-erased data as a `T`.
+    pub fn downcast_ref<T: 'static>(data: &Lock<AnyData>) -> Ref<'_, T> {
+        let guard = data.borrow();
+        assert!(guard.is::<T>());
+        Ref {
+            guard,
+            idx: None,
+            _ty: PhantomData,
+        }
+    }
+    /// Mutably borrows type-erased data as a `T`.
+    pub fn downcast_mut<T: 'static>(data: &Lock<AnyData>) -> RefMut<'_, T> {
+        let guard = data.borrow_mut();
+        assert!(guard.is::<T>());
+        RefMut {
+            guard,
+            idx: None,
+            _ty: PhantomData,
+        }
+    }
+    /// Immutably borrows the element at `idx` of type-erased data that's a `Vec<T>`. The whole
+    /// `Vec` stays borrowed until the `Ref` is dropped.
+    pub fn downcast_index<T: 'static>(data: &Lock<AnyData>, idx: usize) -> Ref<'_, T> {
+        let guard = data.borrow();
+        assert!(idx < guard.downcast_ref::<Vec<T>>().unwrap().len());
+        Ref {
+            guard,
+            idx: Some(idx),
+            _ty: PhantomData,
+        }
+    }
+    /// Mutably borrows the element at `idx` of type-erased data that's a `Vec<T>`. The whole
+    /// `Vec` stays borrowed until the `RefMut` is dropped.
+    pub fn downcast_index_mut<T: 'static>(data: &Lock<AnyData>, idx: usize) -> RefMut<'_, T> {
+        let guard = data.borrow_mut();
+        assert!(idx < guard.downcast_ref::<Vec<T>>().unwrap().len());
+        RefMut {
+            guard,
+            idx: Some(idx),
+            _ty: PhantomData,
+        }
+    }
+}
+#[cfg(feature = "parallel")]
+pub use multi_threaded::*;
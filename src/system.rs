@@ -14,6 +14,8 @@
 //! implements `System` so it can run it; `SystemParamFn`, a trait implemented for
 //! any functions that have only `SystemParam`s as arguments; and `IntoSystem`, a trait
 //! that stores `SystemParamFn`s inside `SystemStores` so they can be executed as `System`s.
+//! Functions that take `&mut World` skip all of this, and are stored in an `ExclusiveSystem`
+//! instead.
 //!
 //! This setup is quite complicated, but is necessary for type-erasure and lifetime-erasure.
 //! Functions have a lot of generics, for their arguments, and lifetimes associated
@@ -22,35 +24,213 @@
 //! method that runs the function. In the future, it may be possible to accomplish this
 //! with less traits and a more organised system, and SECS will definitely switch then if
 //! possible.
+//!
+//! With the `parallel` feature, systems that don't conflict with each other are run on multiple
+//! threads. See the `executor` module.
 
-use crate::_crate_prelude::*;
+use {
+    crate::{
+        _crate_prelude::*,
+        sync::{Lock, MaybeSend, MaybeSync, Shared},
+    },
+    access::Access,
+    alloc::string::String,
+    command::{CommandBuffer, CommandMode},
+    condition::ConditionalSystem,
+    core::{
+        any::type_name,
+        marker::PhantomData,
+        ops::{Deref, DerefMut},
+    },
+    error::{HandledSystem, SystemOutput},
+    order::{Ambiguity, OrderedSystem, SystemOrder},
+    pipe::PipeSystem,
+    stepping::Stepping,
+};
 
+pub mod access;
 pub mod command;
+pub mod condition;
+pub mod diagnostics;
+pub mod dot;
+pub mod error;
+#[cfg(feature = "parallel")]
+pub mod executor;
+pub mod local;
+pub mod order;
+#[cfg(feature = "std")]
+pub mod panic;
+pub mod param_set;
+pub mod pipe;
 pub mod query;
 pub mod resource;
+pub mod stepping;
 
 /// The base trait for all `System`s, which just allows them to be executed
 /// with mutable access to the world. Getting the system's parameters and data
 /// is left up to the trait impl.
+///
+/// Systems can be given an input when they run, and can return an output. Most systems don't
+/// use either, so their input and output are both `()`.
 pub trait System {
-    fn execute(&self, world: &mut World);
+    /// The value given to the system when it runs. See `In`.
+    type In;
+    /// The value the system returns when it's done running.
+    type Out;
+
+    fn execute(&mut self, input: Self::In, world: &mut World) -> Self::Out;
+
+    /// Takes the system's data from the World and stores it in the system, so the system can be
+    /// sent to another thread and run there. See the `executor` module. By default, this just
+    /// runs the system on the current thread.
+    #[cfg(feature = "parallel")]
+    fn prepare(&mut self, input: Self::In, world: &mut World) -> executor::Prepared<Self::Out> {
+        executor::Prepared::Done(self.execute(input, world))
+    }
+    /// Runs the system with the data `prepare` took. This is called on another thread, and only
+    /// if `prepare` returned `Prepared::Task`.
+    #[cfg(feature = "parallel")]
+    fn run_prepared(&mut self) {
+        unreachable!("Tried to run a system that wasn't prepared to run on another thread")
+    }
+    /// Releases the data `prepare` took back into the World, and returns the system's output.
+    /// This is called on the World's thread, after `run_prepared`.
+    #[cfg(feature = "parallel")]
+    fn finish(&mut self, _world: &mut World) -> Self::Out {
+        unreachable!("Tried to finish a system that wasn't prepared to run on another thread")
+    }
+
+    /// The system's name, for errors and debugging. By default, this is the system's type name.
+    fn name(&self) -> &str {
+        type_name::<Self>()
+    }
+
+    /// The data this system accesses. By default, systems are assumed to access the whole
+    /// world mutably, like exclusive systems do.
+    fn access(&self) -> Access {
+        let mut access = Access::default();
+        access.write_world();
+        access
+    }
+
+    /// The system's ordering constraints. See the `order` module. By default, systems have no
+    /// constraints.
+    fn order(&self) -> SystemOrder {
+        SystemOrder::default()
+    }
+
+    /// Where this system's stats are stored in `SystemDiagnostics`, if they've been recorded.
+    /// The World's `Systems` store every system in a `HandledSystem`, which keeps track of this,
+    /// so other systems don't need to.
+    fn stats_idx(&self) -> Option<usize> {
+        None
+    }
+    /// Caches where this system's stats are stored in `SystemDiagnostics`.
+    fn set_stats_idx(&mut self, _idx: usize) {}
 }
 
-/// A struct that stores a system. This is actually the only type that implements
-/// `System`, because it is able to type-erase a lot of the function's generics.
-pub struct SystemStore<Params>(Box<dyn SystemParamFn<Params>>);
-impl<Params> System for SystemStore<Params> {
-    fn execute(&self, world: &mut World) {
-        self.0.execute(world);
+/// A struct that stores a system. This is the type that most systems get stored in, because it
+/// is able to type-erase a lot of the function's generics.
+///
+/// The store also holds the system's state, which persists between runs of the system. Each
+/// system gets its own state, so it's private to that system. See `Local`.
+pub struct SystemStore<F: SystemParamFn<Params>, Params> {
+    function: F,
+    state: F::State,
+    access: Access,
+    /// The data taken by `System::prepare`, until the system finishes. It's in a `Mutex` so the
+    /// store stays `Sync`, but it's only ever accessed through `&mut self`, so it's never locked.
+    #[cfg(feature = "parallel")]
+    task: std::sync::Mutex<Option<executor::StoreTask<F, Params>>>,
+    _params: PhantomData<fn() -> Params>,
+}
+impl<F: SystemParamFn<Params>, Params> System for SystemStore<F, Params>
+where
+    F: MaybeSync,
+    F::State: MaybeSend,
+    F::Data: MaybeSend,
+    F::In: MaybeSend,
+    F::Out: MaybeSend,
+{
+    type In = F::In;
+    type Out = F::Out;
+
+    fn execute(&mut self, input: Self::In, world: &mut World) -> Self::Out {
+        self.function.execute(input, world, &mut self.state)
+    }
+    #[cfg(feature = "parallel")]
+    fn prepare(&mut self, input: Self::In, world: &mut World) -> executor::Prepared<Self::Out> {
+        // Functions that need the World while they run can't run on another thread
+        if F::NEEDS_WORLD {
+            return executor::Prepared::Done(self.execute(input, world));
+        }
+        let data = F::take(world, &mut self.state);
+        *self.task.get_mut().unwrap() = Some(executor::StoreTask::new(input, data));
+        executor::Prepared::Task
+    }
+    #[cfg(feature = "parallel")]
+    fn run_prepared(&mut self) {
+        executor::StoreTask::run(self.task.get_mut().unwrap(), &self.function);
+    }
+    #[cfg(feature = "parallel")]
+    fn finish(&mut self, world: &mut World) -> Self::Out {
+        let task = self.task.get_mut().unwrap().take();
+        task.expect("Tried to finish a system that wasn't prepared")
+            .finish(world, &mut self.state)
+    }
+    fn name(&self) -> &str {
+        type_name::<F>()
+    }
+    fn access(&self) -> Access {
+        self.access.clone()
     }
 }
 
-/// A function with parameters that implement `SystemParam`.
+/// A function with parameters that implement `SystemParam`. The function may also take an
+/// `In` as its first parameter, and may return a value.
+///
+/// `Params` is a function pointer with the same parameters and return type as the function,
+/// eg `fn(&Query<&A>, &Resource<B>) -> bool`. It's only used to tell implementations apart.
 pub trait SystemParamFn<Params> {
-    fn execute(&self, world: &mut World);
+    /// The type inside the function's `In` parameter, or `()` if it doesn't have one.
+    type In;
+    /// The function's return type.
+    type Out;
+    /// The state of all of the function's parameters. This is a tuple of each parameter's
+    /// `WorldData::State`.
+    type State;
+    /// The data taken from the World for all of the function's parameters. This is a tuple of
+    /// each parameter's `SystemParam::Data`.
+    type Data;
+    /// If any of the function's parameters need the World while the function runs. See
+    /// `SystemParam::NEEDS_WORLD`.
+    const NEEDS_WORLD: bool = false;
+
+    /// Creates the function's initial state, from each parameter's default state.
+    fn init_state() -> Self::State;
+    /// The data the function's parameters access.
+    fn access() -> Access;
+
+    /// Takes each parameter's data from the World.
+    fn take(world: &mut World, state: &mut Self::State) -> Self::Data;
+    /// Calls the function with parameters fetched from `data`. Unless `NEEDS_WORLD` is set, this
+    /// doesn't need the World, so it can be called on another thread with `world` set to `None`.
+    fn call(&self, input: Self::In, data: &mut Self::Data, world: Option<&mut World>) -> Self::Out;
+    /// Releases each parameter's data back into the World.
+    fn release(data: Self::Data, world: &mut World, state: &mut Self::State);
+
+    /// Takes the function's data, calls it, and then releases the data.
+    fn execute(&self, input: Self::In, world: &mut World, state: &mut Self::State) -> Self::Out {
+        let mut data = Self::take(world, state);
+        let out = self.call(input, &mut data, Some(world));
+        Self::release(data, world, state);
+        out
+    }
 }
 macro_rules! impl_system_param_fn {
-    ($_unused: ident) => {};
+    ($_unused: ident) => {
+        secs_macros::impl_system_param_fn!();
+    };
     ($_unused: ident $($x: ident)*) => {
         secs_macros::impl_system_param_fn!($($x)*);
         impl_system_param_fn!($($x)*);
@@ -62,23 +242,168 @@ impl_system_param_fn!(A A B C D E F G H I J K L M N O P Q R S T U V W X Y Z);
 /// executed like regular systems.
 pub trait IntoSystem<Result: System> {
     fn into_system(self) -> Result;
+
+    /// Runs this system, then gives its output to another system as that system's input. The
+    /// other system has to take an `In` with this system's output type.
+    fn pipe<Other: System<In = Result::Out>>(
+        self,
+        other: impl IntoSystem<Other>,
+    ) -> PipeSystem<Result, Other>
+    where
+        Self: Sized,
+    {
+        PipeSystem::new(self.into_system(), other.into_system())
+    }
+
+    /// Makes this system only run when another system, `condition`, returns `true`. The
+    /// condition runs first, every time this system would run.
+    fn run_if<Condition: System<In = (), Out = bool>>(
+        self,
+        condition: impl IntoSystem<Condition>,
+    ) -> ConditionalSystem<Result, Condition>
+    where
+        Self: Sized,
+    {
+        ConditionalSystem::new(self.into_system(), condition.into_system())
+    }
+
+    /// Gives this system a label, which other systems can use in `before` and `after`.
+    fn label(self, label: &'static str) -> OrderedSystem<Result>
+    where
+        Self: Sized,
+    {
+        OrderedSystem::new(
+            self.into_system(),
+            SystemOrder {
+                labels: vec![label],
+                ..Default::default()
+            },
+        )
+    }
+    /// Makes this system run before the system with the name or label `other`. See the `order`
+    /// module.
+    fn before(self, other: &'static str) -> OrderedSystem<Result>
+    where
+        Self: Sized,
+    {
+        OrderedSystem::new(
+            self.into_system(),
+            SystemOrder {
+                before: vec![other],
+                ..Default::default()
+            },
+        )
+    }
+    /// Makes this system run after the system with the name or label `other`. See the `order`
+    /// module.
+    fn after(self, other: &'static str) -> OrderedSystem<Result>
+    where
+        Self: Sized,
+    {
+        OrderedSystem::new(
+            self.into_system(),
+            SystemOrder {
+                after: vec![other],
+                ..Default::default()
+            },
+        )
+    }
 }
-impl<F, Params> IntoSystem<SystemStore<Params>> for F
+impl<S: System> IntoSystem<S> for S {
+    fn into_system(self) -> S {
+        self
+    }
+}
+impl<F, Params> IntoSystem<SystemStore<F, Params>> for F
 where
-    F: SystemParamFn<Params> + 'static,
+    F: SystemParamFn<Params> + MaybeSync + 'static,
+    F::State: MaybeSend,
+    F::Data: MaybeSend,
+    F::In: MaybeSend,
+    F::Out: MaybeSend,
 {
-    fn into_system(self) -> SystemStore<Params> {
-        SystemStore(Box::new(self))
+    fn into_system(self) -> SystemStore<F, Params> {
+        SystemStore {
+            function: self,
+            state: F::init_state(),
+            access: F::access(),
+            #[cfg(feature = "parallel")]
+            task: Default::default(),
+            _params: PhantomData,
+        }
+    }
+}
+
+/// A system that has full, mutable access to the `World`, instead of using `SystemParam`s. Any
+/// `fn(&mut World)` can be used as an exclusive system.
+///
+/// Exclusive systems run in the same order as every other system. Note that the World's
+/// `Systems` are borrowed while they run, so an exclusive system can't add new systems or run
+/// the World's systems itself.
+pub struct ExclusiveSystem<F: FnMut(&mut World) -> Out, Out>(F, PhantomData<fn() -> Out>);
+impl<F: FnMut(&mut World) -> Out, Out> System for ExclusiveSystem<F, Out> {
+    type In = ();
+    type Out = Out;
+
+    fn execute(&mut self, _: Self::In, world: &mut World) -> Self::Out {
+        (self.0)(world)
+    }
+    fn name(&self) -> &str {
+        type_name::<F>()
+    }
+    fn access(&self) -> Access {
+        let mut access = Access::default();
+        access.write_world();
+        access
+    }
+}
+impl<F, Out> IntoSystem<ExclusiveSystem<F, Out>> for F
+where
+    F: FnMut(&mut World) -> Out + 'static,
+{
+    fn into_system(self) -> ExclusiveSystem<F, Out> {
+        ExclusiveSystem(self, PhantomData)
+    }
+}
+
+/// The input given to a system. If a system's first parameter is an `In`, it will receive the
+/// value passed to `System::execute` - for example, the output of another system with
+/// `IntoSystem::pipe`.
+#[derive(Debug)]
+pub struct In<T>(pub T);
+impl<T> Deref for In<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl<T> DerefMut for In<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
     }
 }
 
 /// Data that can be taken from and returned to the `World`. System parameters use this trait.
 pub trait WorldData: 'static {
+    /// State that's stored between runs of a system. Each system has its own copy of this
+    /// state. Most data doesn't need any state, and just uses `()`.
+    type State: Default + 'static;
+
     /// Takes data from the `World` to create the data.
-    fn take(world: &mut World) -> Self;
+    fn take(world: &mut World, state: &mut Self::State) -> Self;
 
     /// Releases any taken data back into the World.
-    fn release(self, world: &mut World);
+    fn release(self, world: &mut World, state: &mut Self::State);
+
+    /// Records the components and resources this data accesses when it's borrowed immutably.
+    /// By default, this records nothing.
+    fn access(_access: &mut Access) {}
+    /// Records the components and resources this data accesses when it's borrowed mutably.
+    /// By default, this is the same as `access`.
+    fn access_mut(access: &mut Access) {
+        Self::access(access);
+    }
 }
 
 /// A parameter that may be used in a system. Types that implement this can be used as arguments
@@ -89,41 +414,447 @@ pub trait SystemParam {
     /// The output this `SystemParam` makes. This is essentially `Self`, but with a
     /// different lifetime that will match the system/function's lifetime.
     type Fetch<'a>;
+    /// If this parameter needs the World while the system runs, like `ParamSet` does. Systems
+    /// with a parameter like this always run on the World's thread.
+    const NEEDS_WORLD: bool = false;
 
-    /// Takes the `SystemParam`'s data to make the `SystemParam`.
-    fn fetch(data: &mut Self::Data) -> Self::Fetch<'_>;
+    /// Takes the `SystemParam`'s data to make the `SystemParam`. `world` is only set if the
+    /// system is running on the World's thread; parameters that need it should set
+    /// `NEEDS_WORLD`, and take it out of the `Option`.
+    fn fetch<'a>(data: &'a mut Self::Data, world: &mut Option<&'a mut World>) -> Self::Fetch<'a>;
+
+    /// Records the components and resources this `SystemParam` accesses.
+    fn access(access: &mut Access);
 }
 
 impl<WD: WorldData> SystemParam for &WD {
     type Data = WD;
     type Fetch<'a> = &'a WD;
 
-    fn fetch(data: &mut Self::Data) -> Self::Fetch<'_> {
+    fn fetch<'a>(data: &'a mut Self::Data, _: &mut Option<&'a mut World>) -> Self::Fetch<'a> {
         &(*data)
     }
+
+    fn access(access: &mut Access) {
+        WD::access(access);
+    }
 }
 impl<WD: WorldData> SystemParam for &mut WD {
     type Data = WD;
     type Fetch<'a> = &'a mut WD;
 
-    fn fetch(data: &mut Self::Data) -> Self::Fetch<'_> {
+    fn fetch<'a>(data: &'a mut Self::Data, _: &mut Option<&'a mut World>) -> Self::Fetch<'a> {
         data
     }
+
+    fn access(access: &mut Access) {
+        WD::access_mut(access);
+    }
+}
+
+/// Tuples of `WorldData` are also `WorldData`; each item in the tuple is taken and released in
+/// order. `#[derive(SystemParam)]` uses this to store the data for each of a struct's fields.
+macro_rules! impl_world_data_tuple {
+    ($(($data:ident, $idx:tt)),*) => {
+        impl<$($data: WorldData,)*> WorldData for ($($data,)*) {
+            type State = ($($data::State,)*);
+
+            fn take(world: &mut World, state: &mut Self::State) -> Self {
+                ($($data::take(world, &mut state.$idx),)*)
+            }
+
+            fn release(self, world: &mut World, state: &mut Self::State) {
+                $(self.$idx.release(world, &mut state.$idx);)*
+            }
+
+            fn access(access: &mut Access) {
+                $($data::access(access);)*
+            }
+            fn access_mut(access: &mut Access) {
+                $($data::access_mut(access);)*
+            }
+        }
+    };
 }
+impl_world_data_tuple!((A, 0));
+impl_world_data_tuple!((A, 0), (B, 1));
+impl_world_data_tuple!((A, 0), (B, 1), (C, 2));
+impl_world_data_tuple!((A, 0), (B, 1), (C, 2), (D, 3));
+impl_world_data_tuple!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4));
+impl_world_data_tuple!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5));
+impl_world_data_tuple!((A, 0), (B, 1), (C, 2), (D, 3), (E, 4), (F, 5), (G, 6));
+impl_world_data_tuple!(
+    (A, 0),
+    (B, 1),
+    (C, 2),
+    (D, 3),
+    (E, 4),
+    (F, 5),
+    (G, 6),
+    (H, 7)
+);
+impl_world_data_tuple!(
+    (A, 0),
+    (B, 1),
+    (C, 2),
+    (D, 3),
+    (E, 4),
+    (F, 5),
+    (G, 6),
+    (H, 7),
+    (I, 8)
+);
+impl_world_data_tuple!(
+    (A, 0),
+    (B, 1),
+    (C, 2),
+    (D, 3),
+    (E, 4),
+    (F, 5),
+    (G, 6),
+    (H, 7),
+    (I, 8),
+    (J, 9)
+);
+impl_world_data_tuple!(
+    (A, 0),
+    (B, 1),
+    (C, 2),
+    (D, 3),
+    (E, 4),
+    (F, 5),
+    (G, 6),
+    (H, 7),
+    (I, 8),
+    (J, 9),
+    (K, 10)
+);
+impl_world_data_tuple!(
+    (A, 0),
+    (B, 1),
+    (C, 2),
+    (D, 3),
+    (E, 4),
+    (F, 5),
+    (G, 6),
+    (H, 7),
+    (I, 8),
+    (J, 9),
+    (K, 10),
+    (L, 11)
+);
+
+/// A system the World can run: one without an input or output, that can be sent between threads
+/// with the `parallel` feature. This is implemented for every such system, and is only used for
+/// `Box<dyn ErasedSystem>`, since `MaybeSend` and `MaybeSync` can't be added to a trait object
+/// like `Send` and `Sync` can.
+pub trait ErasedSystem: System<In = (), Out = ()> + MaybeSend + MaybeSync {}
+impl<S: System<In = (), Out = ()> + MaybeSend + MaybeSync> ErasedSystem for S {}
+
+/// The ID of a system registered with `World::register_system`. One-shot systems aren't run
+/// with the rest of the World's systems; they only run when `World::run_system` (or the
+/// `RunSystem` command) is used with their ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SystemId(pub usize);
 
 /// A struct that stores the World's Systems and runs them.
 #[derive(Default)]
-pub struct Systems(Vec<Box<dyn System>>);
+pub struct Systems {
+    /// The systems to run, in the order they run in.
+    pub systems: Vec<Box<dyn ErasedSystem>>,
+    /// Functions that apply state transitions (see the `state` module). The World runs these at
+    /// the start of each frame, before the fixed systems, so every system in a frame sees the
+    /// same state.
+    pub state_transitions: Vec<fn(&mut World)>,
+    /// Where the systems are paused, if stepping is enabled. See the `stepping` module.
+    pub stepping: Option<Stepping>,
+    /// If systems have been added since the systems were last sorted. See the `order` module.
+    pub needs_sort: bool,
+    /// When these systems' commands are applied, and the commands they've deferred. While the
+    /// systems run, the World's `active_commands` points at this. See `CommandBuffer`.
+    pub commands: Shared<Lock<CommandBuffer>>,
+    /// The systems that can run in parallel, as ranges of `systems`, and the `CommandMode` they
+    /// were found for. These are found before the systems run, and cleared when they're sorted.
+    /// See the `executor` module.
+    #[cfg(feature = "parallel")]
+    pub batches: Option<(CommandMode, Vec<core::ops::Range<usize>>)>,
+}
 impl Systems {
-    /// Run every system once.
-    pub fn run(&self, world: &mut World) {
-        for system in &self.0 {
-            system.execute(world);
+    /// Run every system once. Any commands these systems deferred are applied once every system
+    /// has run. Each system is run with `diagnostics::run_traced`, so the World's `SystemHook`s
+    /// and `SystemDiagnostics` see it.
+    ///
+    /// If stepping is enabled, this instead runs from wherever the systems are paused until the
+    /// end of the frame, or until the next breakpoint.
+    ///
+    /// If systems were added since the last run, they're sorted by their ordering constraints
+    /// first. See the `order` module.
+    ///
+    /// With the `parallel` feature, systems that don't conflict run on multiple threads. See the
+    /// `executor` module.
+    pub fn run(&mut self, world: &mut World) {
+        if self.stepping.is_some() {
+            loop {
+                self.step(world);
+                if self.at_frame_start() {
+                    break;
+                }
+                let stepping = self.stepping.as_ref().unwrap();
+                if stepping.is_breakpoint(self.systems[stepping.cursor].name()) {
+                    break;
+                }
+            }
+            return;
+        }
+
+        self.sort();
+        let previous = world.active_commands.replace(self.commands.clone());
+        #[cfg(not(feature = "parallel"))]
+        for system in &mut self.systems {
+            diagnostics::run_traced(system, world);
+        }
+        #[cfg(feature = "parallel")]
+        {
+            let mode = self.command_mode();
+            if self.batches.as_ref().map(|(batched, _)| *batched) != Some(mode) {
+                self.batches = Some((mode, executor::batches(&self.systems, mode)));
+            }
+            for batch in self.batches.as_ref().unwrap().1.clone() {
+                executor::run_batch(&mut self.systems, batch, world);
+            }
+        }
+        world.apply_deferred();
+        world.active_commands = previous;
+    }
+
+    /// Runs the next system, then pauses. Commands these systems deferred are applied after the
+    /// last system in a frame.
+    ///
+    /// Panics if stepping isn't enabled.
+    pub fn step(&mut self, world: &mut World) {
+        let cursor = self
+            .stepping
+            .as_ref()
+            .expect("Tried to step through systems without enabling stepping")
+            .cursor;
+
+        if cursor == 0 {
+            self.sort();
         }
+        let previous = world.active_commands.replace(self.commands.clone());
+        if let Some(system) = self.systems.get_mut(cursor) {
+            diagnostics::run_traced(system, world);
+        }
+
+        let stepping = self.stepping.as_mut().unwrap();
+        stepping.cursor += 1;
+        if stepping.cursor >= self.systems.len() {
+            stepping.cursor = 0;
+            world.apply_deferred();
+        }
+        world.active_commands = previous;
+    }
+
+    /// When these systems' commands are applied. See `CommandMode`.
+    pub fn command_mode(&self) -> CommandMode {
+        self.commands.borrow().mode
+    }
+    /// Sets when these systems' commands are applied. See `CommandMode`.
+    pub fn set_command_mode(&mut self, mode: CommandMode) {
+        self.commands.borrow_mut().mode = mode;
     }
 
-    /// Add a new system to run.
-    pub fn push<Sys: System + 'static>(&mut self, system: impl IntoSystem<Sys>) {
-        self.0.push(Box::new(system.into_system()));
+    /// Sorts the systems by their ordering constraints, if any systems were added since they
+    /// were last sorted.
+    ///
+    /// Panics if the constraints form a cycle.
+    pub fn sort(&mut self) {
+        if self.needs_sort {
+            order::sort(&mut self.systems);
+            #[cfg(feature = "parallel")]
+            {
+                self.batches = None;
+            }
+            self.needs_sort = false;
+        }
+    }
+    /// Finds systems that access the same data in conflicting ways, but aren't ordered
+    /// relative to each other. See the `order` module.
+    pub fn ambiguities(&self) -> Vec<Ambiguity> {
+        order::ambiguities(&self.systems)
+    }
+
+    /// Renders the systems, their ordering, and the data they access as a Graphviz DOT graph.
+    /// See the `dot` module.
+    pub fn to_dot(&self) -> String {
+        dot::to_dot(self)
+    }
+
+    /// The name of the next system that will run, if stepping is enabled.
+    pub fn next_system(&self) -> Option<&str> {
+        let stepping = self.stepping.as_ref()?;
+        self.systems
+            .get(stepping.cursor)
+            .map(|system| system.name())
+    }
+
+    /// Applies any pending state transitions. See `state_transitions`.
+    pub fn apply_state_transitions(&self, world: &mut World) {
+        for transition in &self.state_transitions {
+            transition(world);
+        }
+    }
+
+    /// If the next system to run is the first one in a frame. This is always true if stepping
+    /// isn't enabled.
+    pub fn at_frame_start(&self) -> bool {
+        self.stepping.as_ref().map_or(0, |stepping| stepping.cursor) == 0
+    }
+
+    /// Add a new system to run. The system may return `()` or a `Result<(), E>`; errors are
+    /// sent to the World's `ErrorHandler`.
+    pub fn push<Sys: System<In = ()> + MaybeSend + MaybeSync + 'static>(
+        &mut self,
+        system: impl IntoSystem<Sys>,
+    ) where
+        Sys::Out: SystemOutput,
+    {
+        self.systems
+            .push(Box::new(HandledSystem(system.into_system(), None)));
+        self.needs_sort = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{self as secs, _crate_prelude::*, prelude::*};
+
+    #[derive(Component)]
+    struct Marker;
+
+    fn spawn_markers(world: &mut World) {
+        world.spawn(Marker);
+        world.spawn(Marker);
+    }
+
+    fn count_markers(query: &Query<&Marker>, counts: &mut Resource<Vec<usize>>) {
+        counts.get_mut().push(query.len());
+    }
+
+    fn count_clicks(clicks: &mut Local<u32>, counts: &mut Resource<Vec<usize>>) {
+        **clicks += 1;
+        counts.get_mut().push(**clicks as usize);
+    }
+
+    /// Verifies that one-shot systems only run when asked to, from the World or from commands,
+    /// and keep their state between runs.
+    #[test]
+    fn one_shot_systems() {
+        let mut world = World::default();
+        world.insert_resource(Vec::<usize>::new());
+        let on_click = world.register_system(count_clicks);
+
+        world.run_once();
+        assert!(world.get_resource::<Vec<usize>>().unwrap().is_empty());
+
+        world.run_system(on_click);
+        world.add_system(move |cmds: &mut CommandQueue| cmds.run_system(on_click));
+        world.run_once();
+        world.run_once();
+
+        assert_eq!(*world.get_resource::<Vec<usize>>().unwrap(), [1, 2, 3]);
+    }
+
+    #[derive(SystemParam)]
+    struct Markers<'a> {
+        query: &'a Query<&'static Marker>,
+        counts: &'a mut Resource<Vec<usize>>,
+    }
+
+    fn count_with_bundle(markers: Markers, cmds: &mut CommandQueue) {
+        let count = markers.query.len();
+        markers.counts.get_mut().push(count);
+        cmds.spawn(Marker);
+    }
+
+    #[derive(SystemParam)]
+    struct ConflictingMarkers<'a> {
+        _read: &'a Query<(&'static Marker,)>,
+        _write: &'a Query<&'static mut Marker>,
+    }
+
+    #[derive(SystemParam)]
+    struct Callback<'a> {
+        callback: &'a Resource<fn(usize) -> usize>,
+        counts: &'a mut Resource<Vec<usize>>,
+    }
+
+    fn run_callback(callback: Callback) {
+        let count = (callback.callback.get())(1);
+        callback.counts.get_mut().push(count);
+    }
+
+    /// Verifies that a struct deriving `SystemParam` can be used as a system parameter, and
+    /// reports the accesses of its fields.
+    #[test]
+    fn derived_system_param() {
+        let mut world = World::default();
+        world.insert_resource(Vec::<usize>::new());
+        world.spawn(Marker);
+        world.add_system(count_with_bundle);
+
+        world.run_once();
+        world.run_once();
+        assert_eq!(*world.get_resource::<Vec<usize>>().unwrap(), [1, 2]);
+
+        // The `->` in the callback's type shouldn't end its generics early
+        let mut world = World::default();
+        world.insert_resource(Vec::<usize>::new());
+        world.insert_resource((|x| x + 2) as fn(usize) -> usize);
+        world.add_system(run_callback);
+        world.run_once();
+        assert_eq!(*world.get_resource::<Vec<usize>>().unwrap(), [3]);
+
+        let conflict = world
+            .try_add_system(|_: ConflictingMarkers| {})
+            .unwrap_err();
+        assert!(conflict.access.name.ends_with("Marker"));
+    }
+
+    /// Verifies that exclusive systems run in the same order as parameterized systems.
+    #[test]
+    fn exclusive_system_order() {
+        let mut world = World::default();
+        world.insert_resource(Vec::<usize>::new());
+        world.spawn(Marker);
+        world.add_system(count_markers);
+        world.add_system(spawn_markers);
+        world.add_system(count_markers);
+
+        world.run_once();
+
+        assert_eq!(*world.get_resource::<Vec<usize>>().unwrap(), [1, 3]);
+    }
+
+    fn add_counter(world: &mut World) {
+        world.add_system(count_markers);
+        world.add_fixed_system(count_markers);
+    }
+
+    /// Verifies that systems added by an exclusive system are added once the systems finish
+    /// running, instead of panicking because they're borrowed.
+    #[test]
+    fn add_system_while_running() {
+        let mut world = World::default();
+        world.insert_resource(Vec::<usize>::new());
+        world.spawn(Marker);
+        world.add_system(add_counter);
+
+        world.run_once();
+        assert!(world.get_resource::<Vec<usize>>().unwrap().is_empty());
+        assert_eq!(world.systems.borrow().systems.len(), 2);
+        assert_eq!(world.fixed_systems.borrow().systems.len(), 1);
+        world.run_once();
+        assert_eq!(*world.get_resource::<Vec<usize>>().unwrap(), [1]);
     }
 }
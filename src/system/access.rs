@@ -0,0 +1,270 @@
+//! Tracks the data systems access, so conflicting accesses can be found before the system runs.
+//!
+//! Every `SystemParam` reports the components and resources it reads and writes into an
+//! `Access`. If a system accesses the same data mutably in two places - for example, with
+//! `Query<(&mut A, &A)>`, or with `Query<&mut A>` and `Query<&A>` - its `RefCell`s would panic
+//! when it runs. `World::add_system` catches that ahead of time by checking the system's `Access`
+//! for conflicts.
+
+use {
+    crate::_crate_prelude::*,
+    alloc::string::{String, ToString},
+    core::{
+        any::type_name,
+        fmt::{self, Display, Formatter},
+    },
+};
+
+/// The kinds of data a system can access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataKind {
+    Component,
+    Resource,
+    /// The World itself, taken by a parameter while the system runs. This only shows up in
+    /// `AccessConflict`s; see `Access::take_world`.
+    World,
+}
+impl Display for DataKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Component => write!(f, "component"),
+            Self::Resource => write!(f, "resource"),
+            Self::World => write!(f, "world"),
+        }
+    }
+}
+
+/// A single piece of data a system accesses.
+#[derive(Clone, Copy, Debug)]
+pub struct DataAccess {
+    /// If the data is a component or a resource.
+    pub kind: DataKind,
+    /// The data's `TypeId`.
+    pub id: TypeId,
+    /// The data's type name, for error messages.
+    pub name: &'static str,
+    /// If the data is accessed mutably.
+    pub mutable: bool,
+}
+impl DataAccess {
+    /// Mutable access to the whole World, like an exclusive system has.
+    pub fn world() -> Self {
+        Access::access_of::<World>(DataKind::World, true)
+    }
+    /// Two accesses conflict if they access the same data, and at least one of them is mutable.
+    pub fn conflicts_with(&self, other: &DataAccess) -> bool {
+        self.kind == other.kind && self.id == other.id && (self.mutable || other.mutable)
+    }
+}
+
+/// All of the data a system accesses.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    /// Every component and resource the system reads or writes.
+    pub data: Vec<DataAccess>,
+    /// If the system has mutable access to the entire world. Exclusive systems do this.
+    pub world: bool,
+    /// If the system queues commands. In the `Immediate` command mode, the commands are applied
+    /// as soon as the system finishes, so systems after it can see them.
+    pub commands: bool,
+    /// If one of the system's parameters takes the World while the system runs. See
+    /// `take_world`.
+    pub takes_world: bool,
+    /// Accesses that conflicted with another access in the same system.
+    pub conflicts: Vec<DataAccess>,
+}
+impl Access {
+    /// Records that a component is read.
+    pub fn read_component<C: 'static>(&mut self) {
+        self.add(Self::access_of::<C>(DataKind::Component, false));
+    }
+    /// Records that a component is written.
+    pub fn write_component<C: 'static>(&mut self) {
+        self.add(Self::access_of::<C>(DataKind::Component, true));
+    }
+    /// Records that a resource is read.
+    pub fn read_resource<R: 'static>(&mut self) {
+        self.add(Self::access_of::<R>(DataKind::Resource, false));
+    }
+    /// Records that a resource is written.
+    pub fn write_resource<R: 'static>(&mut self) {
+        self.add(Self::access_of::<R>(DataKind::Resource, true));
+    }
+    /// Records that the system has mutable access to the entire world.
+    pub fn write_world(&mut self) {
+        self.world = true;
+    }
+    /// Records that a parameter takes the World while the system runs, like a `ParamSet` does
+    /// (see `SystemParam::NEEDS_WORLD`). There's only one World to take, so this conflicts if
+    /// it's recorded twice.
+    pub fn take_world(&mut self) {
+        if self.takes_world {
+            self.conflicts.push(DataAccess::world());
+        }
+        self.takes_world = true;
+    }
+    /// Records that the system queues commands.
+    pub fn queue_commands(&mut self) {
+        self.commands = true;
+    }
+
+    /// Records an access, and checks if it conflicts with any access that's already been
+    /// recorded.
+    pub fn add(&mut self, access: DataAccess) {
+        if self.data.iter().any(|other| other.conflicts_with(&access)) {
+            self.conflicts.push(access);
+        }
+        self.data.push(access);
+    }
+    /// Adds every access from another `Access`, checking them for conflicts with the accesses
+    /// already recorded here. Accesses in `other` aren't checked against each other.
+    pub fn extend(&mut self, other: &Access) {
+        for access in &other.data {
+            if self
+                .data
+                .iter()
+                .any(|existing| existing.conflicts_with(access))
+            {
+                self.conflicts.push(*access);
+            }
+        }
+        if self.takes_world && other.takes_world {
+            self.conflicts.push(DataAccess::world());
+        }
+        self.extend_unchecked(other);
+    }
+    /// Adds every access from another `Access`, *without* checking for conflicts between the
+    /// two. This is for data that's never used at the same time, like the parameters in a
+    /// `ParamSet` or two piped systems.
+    pub fn extend_unchecked(&mut self, other: &Access) {
+        self.world |= other.world;
+        self.commands |= other.commands;
+        self.takes_world |= other.takes_world;
+        self.conflicts.extend_from_slice(&other.conflicts);
+        self.data.extend_from_slice(&other.data);
+    }
+
+    /// Checks if two systems with these accesses could run at the same time without
+    /// conflicting.
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        !self.world && !other.world && self.first_conflict(other).is_none()
+    }
+    /// Finds the first piece of data that both accesses use, where at least one of them uses it
+    /// mutably. This ignores access to the entire world.
+    pub fn first_conflict(&self, other: &Access) -> Option<DataAccess> {
+        self.data
+            .iter()
+            .find(|access| other.data.iter().any(|o| o.conflicts_with(access)))
+            .copied()
+    }
+
+    /// Returns an error if any of the recorded accesses conflicted. `system` is the name of the
+    /// system these accesses are for.
+    pub fn check(&self, system: &str) -> Result<(), AccessConflict> {
+        match self.conflicts.first() {
+            Some(access) => Err(AccessConflict {
+                system: system.to_string(),
+                access: *access,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn access_of<T: 'static>(kind: DataKind, mutable: bool) -> DataAccess {
+        DataAccess {
+            kind,
+            id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+            mutable,
+        }
+    }
+}
+
+/// The error for a system that accesses the same data in conflicting ways.
+#[derive(Clone, Debug)]
+pub struct AccessConflict {
+    /// The name of the system.
+    pub system: String,
+    /// The access that conflicted with another access in the system.
+    pub access: DataAccess,
+}
+impl Display for AccessConflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.access.kind == DataKind::World {
+            return write!(
+                f,
+                "System `{}` has more than one parameter that takes the World while it runs, like \
+                a `ParamSet`. A system can only have one.",
+                self.system
+            );
+        }
+        write!(
+            f,
+            "System `{}` has conflicting accesses to the {} `{}`: it's borrowed mutably and \
+            borrowed again elsewhere in the same system. Use a `ParamSet` to access it in \
+            multiple parameters.",
+            self.system, self.access.kind, self.access.name
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{self as secs, _crate_prelude::*, prelude::*},
+        alloc::string::ToString,
+    };
+
+    #[derive(Component)]
+    struct Transform;
+    #[derive(Component)]
+    struct Velocity;
+
+    fn same_query(_: &Query<(&mut Transform, &Transform)>) {}
+    fn two_queries(_: &Query<&mut Transform>, _: &Query<(&Velocity, &Transform)>) {}
+    fn two_resources(_: &mut Resource<u32>, _: &Resource<u32>) {}
+
+    fn reads(_: &Query<&Transform>, _: &Query<(&Transform, &Velocity)>, _: &Resource<u32>) {}
+    fn disjoint(_: &Query<&mut Transform>, _: &Query<&mut Velocity>) {}
+    fn param_set(_: ParamSet<(Query<&mut Transform>, Query<&Transform>)>) {}
+    fn param_set_conflict(
+        _: ParamSet<(Query<&mut Transform>, Query<&Velocity>)>,
+        _: &Query<&Transform>,
+    ) {
+    }
+
+    /// Verifies that systems with conflicting accesses are rejected, with an error naming the
+    /// system and the data.
+    #[test]
+    fn rejects_conflicts() {
+        let mut world = World::default();
+
+        let conflict = world.try_add_system(same_query).unwrap_err();
+        assert!(conflict.system.ends_with("same_query"));
+        assert!(conflict.access.name.ends_with("Transform"));
+        let message = conflict.to_string();
+        assert!(message.contains("same_query") && message.contains("component"));
+
+        assert!(world.try_add_system(two_queries).is_err());
+        let conflict = world.try_add_system(two_resources).unwrap_err();
+        assert!(conflict.to_string().contains("resource `u32`"));
+        assert!(world.try_add_system(param_set_conflict).is_err());
+    }
+
+    /// Verifies that systems without conflicting accesses are accepted.
+    #[test]
+    fn accepts_compatible() {
+        let mut world = World::default();
+
+        assert!(world.try_add_system(reads).is_ok());
+        assert!(world.try_add_system(disjoint).is_ok());
+        assert!(world.try_add_system(param_set).is_ok());
+        assert!(world.try_add_system(|_: &mut World| {}).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicting accesses")]
+    fn add_system_panics() {
+        World::default().add_system(same_query);
+    }
+}
@@ -1,10 +1,16 @@
 use {
-    crate::{_crate_prelude::*, entity::Bundle, system::WorldData},
+    crate::{
+        _crate_prelude::*,
+        entity::Bundle,
+        sync::{AnyData, MaybeSend, MaybeSync},
+        system::{access::Access, SystemId, WorldData},
+    },
     core::ops::{Deref, DerefMut},
 };
 
 /// This is what systems will use to queue commands for the world. The commands will be applied
-/// once the system finishes running - *not* while it's running.
+/// once the system finishes running - *not* while it's running. If the `CommandMode` of the
+/// `Systems` it's running in is `Deferred`, they're applied even later; see `CommandMode`.
 ///
 /// Commands are applied after the system finishes running to avoid conflicting data. For example,
 /// if a system tried to mutably query a component and insert an entity at the same time, it would
@@ -15,6 +21,57 @@ pub struct CommandQueue {
     commands: Vec<Command>,
 }
 
+/// Controls when the commands from `CommandQueue`s are applied to the World. Each `Systems` (the
+/// World's main systems, its fixed systems, and each group of state systems) has its own mode;
+/// see `Systems::set_command_mode`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommandMode {
+    /// Commands are applied as soon as the system that queued them finishes running. This means
+    /// an entity spawned by one system is visible to the next system.
+    #[default]
+    Immediate,
+    /// Commands are buffered in the `Systems` that queued them, and only applied at sync points:
+    /// when the `apply_deferred` system runs in those `Systems`, or when they finish running.
+    /// This lets every system in a frame see the same entities, and applies commands in batches.
+    Deferred,
+}
+
+/// The command buffer for a `Systems`: when its commands are applied, and the commands it's
+/// deferred. Each `Systems` has its own buffer, so the fixed systems' deferred commands are
+/// applied when the fixed systems finish, and `apply_deferred` in the main systems doesn't apply
+/// them early.
+#[derive(Default)]
+pub struct CommandBuffer {
+    /// When commands are applied.
+    pub mode: CommandMode,
+    /// Commands that have been queued, but not applied yet, because the `mode` is `Deferred`.
+    pub deferred: Vec<Command>,
+}
+impl CommandBuffer {
+    /// A buffer that defers every command released into it. This is used to hold commands back
+    /// until it's safe to apply them, like a `ParamSet` does until its system finishes.
+    pub fn deferred() -> Self {
+        Self {
+            mode: CommandMode::Deferred,
+            deferred: Vec::new(),
+        }
+    }
+    /// Releases every command in this buffer, as if they'd all been queued by one
+    /// `CommandQueue`: they're applied, unless the World's active buffer defers them.
+    pub fn release(&mut self, world: &mut World) {
+        let queue = CommandQueue {
+            commands: core::mem::take(&mut self.deferred),
+        };
+        queue.release(world, &mut ());
+    }
+}
+
+/// A system that applies every command the `Systems` it's running in have deferred. Add this
+/// between two systems to make commands from the first visible to the second.
+pub fn apply_deferred(world: &mut World) {
+    world.apply_deferred();
+}
+
 /// The actual Commands that can be applied to a World.
 pub enum Command {
     /// Finish building an EntityBuilder.
@@ -23,6 +80,11 @@ pub enum Command {
     DespawnEntity(usize),
     /// Exits the loop started by `World.run()`.
     ExitRunLoop,
+    /// Exits the loop started by `World.run()`, and makes `run()` return the value. This can be
+    /// used to report why the loop stopped, like an exit code.
+    ExitRunLoopWith(Box<AnyData>),
+    /// Runs a one-shot system by its ID. See `World::register_system`.
+    RunSystem(SystemId),
 }
 
 impl CommandQueue {
@@ -40,6 +102,16 @@ impl CommandQueue {
     pub fn exit_run_loop(&mut self) {
         self.commands.push(Command::ExitRunLoop);
     }
+    /// Exits the loop started by `World.run()`, and makes `run()` return `value`.
+    pub fn exit_run_loop_with(&mut self, value: impl Any + MaybeSend + MaybeSync) {
+        self.commands
+            .push(Command::ExitRunLoopWith(Box::new(value)));
+    }
+
+    /// Runs a one-shot system by its ID. See `World::register_system`.
+    pub fn run_system(&mut self, system: SystemId) {
+        self.commands.push(Command::RunSystem(system));
+    }
 }
 impl Deref for CommandQueue {
     type Target = Vec<Command>;
@@ -56,11 +128,146 @@ impl DerefMut for CommandQueue {
 
 /// Allows the `CommandQueue` struct to be used as a system parameter.
 impl WorldData for CommandQueue {
-    fn take(_: &mut World) -> Self {
+    type State = ();
+
+    fn take(_: &mut World, _: &mut Self::State) -> Self {
         Self::default()
     }
 
-    fn release(self, world: &mut World) {
-        world.apply_commands(self.commands);
+    /// Systems that aren't running in a `Systems`, like ones run with `World::run_system_once`,
+    /// always apply their commands immediately.
+    fn release(self, world: &mut World, _: &mut Self::State) {
+        let deferred = world
+            .active_commands
+            .clone()
+            .filter(|buffer| buffer.borrow().mode == CommandMode::Deferred);
+        match deferred {
+            Some(buffer) => buffer.borrow_mut().deferred.extend(self.commands),
+            None => world.apply_commands(self.commands),
+        }
+    }
+
+    fn access(access: &mut Access) {
+        access.queue_commands();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{self as secs, _crate_prelude::*, prelude::*, time::tests::FakeClock},
+        core::time::Duration,
+    };
+
+    #[derive(Component)]
+    struct Bullet;
+
+    fn spawn_bullet(cmds: &mut CommandQueue) {
+        cmds.spawn(Bullet);
+    }
+
+    fn count_bullets(query: &Query<&Bullet>, counts: &mut Resource<Vec<usize>>) {
+        counts.get_mut().push(query.len());
+    }
+
+    /// Verifies that commands are applied between systems in the `Immediate` mode, but only at
+    /// sync points in the `Deferred` mode.
+    #[test]
+    fn command_modes() {
+        let mut immediate = World::default();
+        immediate.insert_resource(Vec::<usize>::new());
+        immediate.spawn(Bullet);
+        immediate.add_system(spawn_bullet);
+        immediate.add_system(count_bullets);
+        immediate.run_once();
+        assert_eq!(*immediate.get_resource::<Vec<usize>>().unwrap(), [2]);
+
+        let mut deferred = World::default();
+        deferred.set_command_mode(CommandMode::Deferred);
+        deferred.insert_resource(Vec::<usize>::new());
+        deferred.spawn(Bullet);
+        deferred.add_system(spawn_bullet);
+        deferred.add_system(count_bullets);
+        deferred.run_once();
+        deferred.run_once();
+        assert_eq!(*deferred.get_resource::<Vec<usize>>().unwrap(), [1, 2]);
+        assert!(deferred
+            .systems
+            .borrow()
+            .commands
+            .borrow()
+            .deferred
+            .is_empty());
+
+        let mut synced = World::default();
+        synced.set_command_mode(CommandMode::Deferred);
+        synced.insert_resource(Vec::<usize>::new());
+        synced.spawn(Bullet);
+        synced.add_system(spawn_bullet);
+        synced.add_system(count_bullets);
+        synced.add_system(apply_deferred);
+        synced.add_system(count_bullets);
+        synced.run_once();
+        assert_eq!(*synced.get_resource::<Vec<usize>>().unwrap(), [1, 2]);
+    }
+
+    /// Verifies that the fixed systems have their own command mode and buffer, which is applied
+    /// when the fixed systems finish.
+    #[test]
+    fn schedule_buffers() {
+        let clock = FakeClock::default();
+        let mut world = World::default();
+        world.set_clock(clock.clone());
+        world.get_resource_mut::<Time>().unwrap().fixed_step = Duration::from_millis(10);
+        world
+            .fixed_systems
+            .borrow_mut()
+            .set_command_mode(CommandMode::Deferred);
+        world.insert_resource(Vec::<usize>::new());
+        world.spawn(Bullet);
+        world.add_fixed_system(spawn_bullet);
+        world.add_fixed_system(count_bullets);
+        world.add_system(spawn_bullet);
+        world.add_system(count_bullets);
+
+        clock.advance(10);
+        world.run_once();
+        // The fixed systems' bullet is applied once they finish, and the main systems are still
+        // `Immediate`
+        assert_eq!(*world.get_resource::<Vec<usize>>().unwrap(), [1, 3]);
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum ExitReason {
+        TooManyBullets,
+    }
+
+    fn limit_bullets(query: &Query<&Bullet>, cmds: &mut CommandQueue) {
+        if query.len() >= 5 {
+            cmds.exit_run_loop_with(ExitReason::TooManyBullets);
+        }
+    }
+
+    /// Verifies that the run loop stops after a number of runs, when a predicate is true, or
+    /// with a value from the `ExitRunLoopWith` command.
+    #[test]
+    fn run_loop_control() {
+        let mut world = World::default();
+        world.spawn(Bullet);
+        world.add_system(spawn_bullet);
+        assert!(world.run_for(2).is_none());
+        assert_eq!(world.storage.num_entities, 3);
+
+        let exit = world.run_until(|world| world.storage.num_entities >= 4);
+        assert!(exit.is_none());
+        assert_eq!(world.storage.num_entities, 4);
+
+        world.add_system(limit_bullets);
+        let exit = world.run_for(100).unwrap();
+        assert_eq!(
+            exit.downcast_ref::<ExitReason>(),
+            Some(&ExitReason::TooManyBullets)
+        );
+        assert_eq!(world.storage.num_entities, 5);
     }
 }
@@ -0,0 +1,64 @@
+#[cfg(feature = "parallel")]
+use crate::system::executor::Prepared;
+use crate::{
+    _crate_prelude::*,
+    system::{access::Access, order::SystemOrder, System},
+};
+
+/// A system that only runs if another system - its condition - returns `true`. These are made
+/// with `IntoSystem::run_if`.
+///
+/// Since the system might not run, its output is wrapped in an `Option`. Systems added to the
+/// World can still return `Result`s; errors are only handled if the system actually ran.
+pub struct ConditionalSystem<S: System, Condition: System<In = (), Out = bool>> {
+    system: S,
+    condition: Condition,
+}
+impl<S: System, Condition: System<In = (), Out = bool>> ConditionalSystem<S, Condition> {
+    /// Make `system` only run when `condition` returns `true`.
+    pub fn new(system: S, condition: Condition) -> Self {
+        Self { system, condition }
+    }
+}
+impl<S: System, Condition: System<In = (), Out = bool>> System for ConditionalSystem<S, Condition> {
+    type In = S::In;
+    type Out = Option<S::Out>;
+
+    fn execute(&mut self, input: Self::In, world: &mut World) -> Self::Out {
+        if self.condition.execute((), world) {
+            Some(self.system.execute(input, world))
+        } else {
+            None
+        }
+    }
+    /// The condition runs on the current thread, and only the system's task can run on another.
+    #[cfg(feature = "parallel")]
+    fn prepare(&mut self, input: Self::In, world: &mut World) -> Prepared<Self::Out> {
+        if self.condition.execute((), world) {
+            self.system.prepare(input, world).map(Some)
+        } else {
+            Prepared::Done(None)
+        }
+    }
+    #[cfg(feature = "parallel")]
+    fn run_prepared(&mut self) {
+        self.system.run_prepared();
+    }
+    /// This is only called if the system was prepared, so the condition must have passed.
+    #[cfg(feature = "parallel")]
+    fn finish(&mut self, world: &mut World) -> Self::Out {
+        Some(self.system.finish(world))
+    }
+    fn name(&self) -> &str {
+        self.system.name()
+    }
+    /// The condition runs before the system, so they can't conflict with each other.
+    fn access(&self) -> Access {
+        let mut access = self.system.access();
+        access.extend_unchecked(&self.condition.access());
+        access
+    }
+    fn order(&self) -> SystemOrder {
+        self.system.order()
+    }
+}
@@ -0,0 +1,207 @@
+//! Profiling and tracing for systems.
+//!
+//! If the World has a `SystemDiagnostics` resource, `Systems::run` records how many times each
+//! system has run and how long it took. Durations are measured with the World's `Clock` (see
+//! `World::set_clock`), so they're only recorded if the World has a clock.
+//!
+//! `SystemHook`s are called before and after every system runs, and can be added with
+//! `World::add_system_hook`. They're meant for plugging in tracing or logging libraries.
+
+use {
+    crate::{_crate_prelude::*, system::ErasedSystem},
+    alloc::string::{String, ToString},
+    core::time::Duration,
+};
+
+/// Statistics for a single system. See `SystemDiagnostics`.
+#[derive(Clone, Debug, Default)]
+pub struct SystemStats {
+    /// The system's name, which is its type name for function systems.
+    pub name: String,
+    /// How many times the system has run.
+    pub runs: u64,
+    /// How long the system took the last time it ran.
+    pub last_duration: Duration,
+    /// The total time the system has spent running.
+    pub total_duration: Duration,
+}
+impl SystemStats {
+    /// The average time the system takes to run.
+    pub fn average_duration(&self) -> Duration {
+        u32::try_from(self.runs)
+            .ok()
+            .filter(|runs| *runs > 0)
+            .map(|runs| self.total_duration / runs)
+            .unwrap_or_default()
+    }
+}
+
+/// A resource storing `SystemStats` for every system that's run. Insert this resource into the
+/// World to start recording stats.
+///
+/// Each system gets its own stats, even if several systems have the same name. Systems cache
+/// where their stats are (see `System::stats_idx`), so recording them doesn't search by name.
+#[derive(Debug, Default)]
+pub struct SystemDiagnostics {
+    /// Stats for each system, in the order the systems first ran.
+    pub systems: Vec<SystemStats>,
+}
+impl SystemDiagnostics {
+    /// Gets the stats for the first system named `name`.
+    pub fn get(&self, name: &str) -> Option<&SystemStats> {
+        self.systems.iter().find(|stats| stats.name == name)
+    }
+
+    /// Records a run of `system`. If the system's cached stats index doesn't point at its stats
+    /// (because it hasn't run yet, or the resource was replaced), it gets new stats.
+    pub fn record(&mut self, system: &mut dyn ErasedSystem, duration: Duration) {
+        let idx = match system.stats_idx() {
+            Some(idx)
+                if self
+                    .systems
+                    .get(idx)
+                    .is_some_and(|s| s.name == system.name()) =>
+            {
+                idx
+            }
+            _ => {
+                self.systems.push(SystemStats {
+                    name: system.name().to_string(),
+                    ..Default::default()
+                });
+                system.set_stats_idx(self.systems.len() - 1);
+                self.systems.len() - 1
+            }
+        };
+        let stats = &mut self.systems[idx];
+        stats.runs += 1;
+        stats.last_duration = duration;
+        stats.total_duration += duration;
+    }
+}
+
+/// Code that runs before and after every system. See `World::add_system_hook`.
+pub trait SystemHook {
+    /// Called right before the system named `system` runs.
+    fn before_system(&mut self, _system: &str, _world: &World) {}
+    /// Called right after the system named `system` runs. `duration` is how long it took, if
+    /// the World has a clock.
+    fn after_system(&mut self, _system: &str, _duration: Option<Duration>, _world: &World) {}
+}
+
+/// Runs a system, calling the World's hooks and recording its stats. `Systems::run` uses this for
+/// every system it runs.
+///
+/// With the `std` feature, the system is run with `panic::run_isolated`, which may replace it
+/// with a disabled system if it panics.
+pub fn run_traced(system: &mut Box<dyn ErasedSystem>, world: &mut World) {
+    before_system(system.name(), world);
+
+    let start = world.clock.as_ref().map(|clock| clock.now());
+    #[cfg(feature = "std")]
+    crate::system::panic::run_isolated(system, world);
+    #[cfg(not(feature = "std"))]
+    system.execute((), world);
+    let duration = start.map(|start| world.clock.as_ref().unwrap().now().saturating_sub(start));
+
+    after_system(&mut **system, duration, world);
+}
+
+/// Calls the World's hooks before the system named `system` runs.
+pub fn before_system(system: &str, world: &mut World) {
+    // Hooks are taken out of the World while they run, so they can't see themselves
+    let mut hooks = core::mem::take(&mut world.system_hooks);
+    for hook in &mut hooks {
+        hook.before_system(system, world);
+    }
+    world.system_hooks = hooks;
+}
+
+/// Records the stats for a run of `system`, then calls the World's hooks.
+pub fn after_system(system: &mut dyn ErasedSystem, duration: Option<Duration>, world: &mut World) {
+    if let Some(mut diagnostics) = world.get_resource_mut::<SystemDiagnostics>() {
+        diagnostics.record(system, duration.unwrap_or_default());
+    }
+
+    let mut hooks = core::mem::take(&mut world.system_hooks);
+    for hook in &mut hooks {
+        hook.after_system(system.name(), duration, world);
+    }
+    world.system_hooks = hooks;
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{prelude::*, time::tests::FakeClock},
+        alloc::rc::Rc,
+        core::cell::RefCell,
+    };
+
+    /// A hook that logs every call.
+    struct Tracer(Rc<RefCell<Vec<String>>>);
+    impl SystemHook for Tracer {
+        fn before_system(&mut self, system: &str, _: &World) {
+            self.0.borrow_mut().push(alloc::format!("before {system}"));
+        }
+        fn after_system(&mut self, system: &str, _: Option<Duration>, _: &World) {
+            self.0.borrow_mut().push(alloc::format!("after {system}"));
+        }
+    }
+
+    /// Takes 3ms to run, according to the fake clock.
+    fn slow_system(clock: &Resource<FakeClock>) {
+        clock.get().advance(3);
+    }
+
+    /// Verifies that hooks run around each system, and that stats are recorded.
+    #[test]
+    fn diagnostics() {
+        let clock = FakeClock::default();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut world = World::default();
+        world.set_clock(clock.clone());
+        world.insert_resource(SystemDiagnostics::default());
+        world.insert_resource(clock);
+        world.add_system_hook(Tracer(log.clone()));
+        world.add_system(slow_system);
+
+        world.run_once();
+        world.run_once();
+
+        let name = core::any::type_name_of_val(&slow_system);
+        assert_eq!(
+            *log.borrow(),
+            [
+                alloc::format!("before {name}"),
+                alloc::format!("after {name}"),
+                alloc::format!("before {name}"),
+                alloc::format!("after {name}"),
+            ]
+        );
+
+        let diagnostics = world.get_resource::<SystemDiagnostics>().unwrap();
+        let stats = diagnostics.get(name).unwrap();
+        assert_eq!(stats.runs, 2);
+        assert_eq!(stats.last_duration, Duration::from_millis(3));
+        assert_eq!(stats.total_duration, Duration::from_millis(6));
+        assert_eq!(stats.average_duration(), Duration::from_millis(3));
+    }
+
+    /// Verifies that systems with the same name get separate stats.
+    #[test]
+    fn same_name() {
+        let mut world = World::default();
+        world.insert_resource(SystemDiagnostics::default());
+        world.insert_resource(FakeClock::default());
+        world.add_system(slow_system);
+        world.add_system(slow_system);
+
+        world.run_once();
+
+        let diagnostics = world.get_resource::<SystemDiagnostics>().unwrap();
+        assert_eq!(diagnostics.systems.len(), 2);
+        assert!(diagnostics.systems.iter().all(|stats| stats.runs == 1));
+    }
+}
@@ -0,0 +1,178 @@
+//! Renders `Systems` as a Graphviz DOT graph, for reviewing large schedules visually.
+//!
+//! Each system is a box, labelled with its name. Solid arrows between systems are ordering
+//! constraints (see the `order` module), pointing from the system that runs first. Every
+//! component and resource a system accesses is an ellipse, and resources are filled in. Dashed
+//! arrows point from data a system reads, and bold arrows point to data a system writes.
+//! Exclusive systems, which access the whole World, are drawn with a double border.
+//!
+//! `World::to_dot` renders the main and fixed systems together, each in its own cluster. State
+//! systems aren't included, since the World doesn't know every state type; see `clusters_to_dot`
+//! for rendering them.
+//!
+//! The output is plain text, so it can be saved to a file and rendered with `dot -Tsvg`.
+
+use {
+    crate::{
+        _crate_prelude::*,
+        system::{
+            access::{DataAccess, DataKind},
+            order, Systems,
+        },
+    },
+    alloc::string::String,
+    core::fmt::Write,
+};
+
+/// Renders `systems` as a DOT graph. See the module docs.
+pub fn to_dot(systems: &Systems) -> String {
+    render(&[("", systems)], false)
+}
+
+/// Renders several groups of systems as one DOT graph, with each group in its own labelled
+/// cluster. Components and resources are drawn once, outside the clusters, so it's easy to see
+/// which groups share data. `World::to_dot` uses this for the main and fixed systems; a state's
+/// `OnEnter` and `OnExit` groups can be rendered by passing them from its `StateSystems`.
+pub fn clusters_to_dot(groups: &[(&str, &Systems)]) -> String {
+    render(groups, true)
+}
+
+/// Renders the groups of systems, either in clusters or straight into the graph.
+fn render(groups: &[(&str, &Systems)], clustered: bool) -> String {
+    let mut dot = String::from("digraph systems {\n    rankdir=LR;\n");
+    // Edges and data nodes are written after the systems, so data nodes aren't put in a cluster
+    let mut edges = String::new();
+    // The data nodes that have been written so far
+    let mut data: Vec<DataAccess> = Vec::new();
+    // Systems are numbered across every group, so their nodes are unique
+    let mut first = 0;
+
+    for (cluster, (label, systems)) in groups.iter().enumerate() {
+        let indent = if clustered { "        " } else { "    " };
+        if clustered {
+            writeln!(dot, "    subgraph cluster{cluster} {{").unwrap();
+            writeln!(dot, "        label=\"{}\";", escape(label)).unwrap();
+        }
+
+        for (idx, system) in systems.systems.iter().enumerate() {
+            let idx = first + idx;
+            let access = system.access();
+            let shape = if access.world {
+                "box, peripheries=2"
+            } else {
+                "box"
+            };
+            writeln!(
+                dot,
+                "{indent}system{idx} [label=\"{}\", shape={shape}];",
+                escape(system.name())
+            )
+            .unwrap();
+
+            for access in &access.data {
+                let node = match data
+                    .iter()
+                    .position(|other| other.kind == access.kind && other.id == access.id)
+                {
+                    Some(node) => node,
+                    None => {
+                        let kind = match access.kind {
+                            DataKind::Component => "",
+                            DataKind::Resource | DataKind::World => ", style=filled",
+                        };
+                        writeln!(
+                            edges,
+                            "    data{} [label=\"{}\", shape=ellipse{kind}];",
+                            data.len(),
+                            escape(access.name)
+                        )
+                        .unwrap();
+                        data.push(*access);
+                        data.len() - 1
+                    }
+                };
+                if access.mutable {
+                    writeln!(edges, "    system{idx} -> data{node} [style=bold];").unwrap();
+                } else {
+                    writeln!(edges, "    data{node} -> system{idx} [style=dashed];").unwrap();
+                }
+            }
+        }
+
+        for (idx, successors) in order::successors(&systems.systems).iter().enumerate() {
+            for successor in successors {
+                writeln!(
+                    edges,
+                    "    system{} -> system{};",
+                    first + idx,
+                    first + successor
+                )
+                .unwrap();
+            }
+        }
+
+        if clustered {
+            dot.push_str("    }\n");
+        }
+        first += systems.systems.len();
+    }
+
+    dot.push_str(&edges);
+    dot.push_str("}\n");
+    dot
+}
+
+/// Escapes a name so it can be used in a quoted DOT string.
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{self as secs, prelude::*};
+
+    #[derive(Component)]
+    struct Velocity;
+
+    fn accelerate(_: &Query<&mut Velocity>) {}
+    fn print_speed(_: &Query<&Velocity>, _: &Resource<u32>) {}
+
+    /// Verifies that systems, their data, and their ordering appear in the graph.
+    #[test]
+    fn dot_graph() {
+        let mut world = World::default();
+        world.add_system(print_speed.after("accelerate"));
+        world.add_system(accelerate);
+        world.add_system(apply_deferred);
+
+        let dot = world.systems.borrow().to_dot();
+        assert!(dot.starts_with("digraph systems {"));
+        assert!(dot.contains("print_speed\", shape=box];"));
+        assert!(dot.contains("apply_deferred\", shape=box, peripheries=2];"));
+        assert!(dot.contains("Velocity\", shape=ellipse];"));
+        assert!(dot.contains("u32\", shape=ellipse, style=filled];"));
+        // print_speed reads Velocity and u32, accelerate writes Velocity, and accelerate runs
+        // before print_speed
+        assert!(dot.contains("data0 -> system0 [style=dashed];"));
+        assert!(dot.contains("data1 -> system0 [style=dashed];"));
+        assert!(dot.contains("system1 -> data0 [style=bold];"));
+        assert!(dot.contains("system1 -> system0;"));
+    }
+
+    /// Verifies that the World's graph puts the main and fixed systems in separate clusters.
+    #[test]
+    fn world_clusters() {
+        let mut world = World::default();
+        world.add_system(print_speed);
+        world.add_fixed_system(accelerate);
+
+        let dot = world.to_dot();
+        assert!(dot.contains("subgraph cluster0 {\n        label=\"systems\";"));
+        assert!(dot.contains("subgraph cluster1 {\n        label=\"fixed systems\";"));
+        assert!(dot.contains("        system0 [label=\""));
+        assert!(dot.contains("        system1 [label=\""));
+        // Both clusters share the Velocity node
+        assert!(dot.contains("data0 -> system0 [style=dashed];"));
+        assert!(dot.contains("system1 -> data0 [style=bold];"));
+    }
+}
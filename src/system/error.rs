@@ -0,0 +1,232 @@
+#[cfg(feature = "parallel")]
+use crate::system::executor::Prepared;
+use {
+    crate::{
+        _crate_prelude::*,
+        sync::{MaybeSend, MaybeSync},
+        system::{access::Access, order::SystemOrder, System},
+    },
+    alloc::string::{String, ToString},
+    core::fmt::{self, Debug, Display, Formatter},
+};
+
+/// An error returned by a system. With the `parallel` feature, errors are `Send` and `Sync`, so
+/// they can be stored in the `SystemErrors` resource.
+#[cfg(not(feature = "parallel"))]
+pub type BoxedError = Box<dyn Debug>;
+/// An error returned by a system. With the `parallel` feature, errors are `Send` and `Sync`, so
+/// they can be stored in the `SystemErrors` resource.
+#[cfg(feature = "parallel")]
+pub type BoxedError = Box<dyn Debug + Send + Sync>;
+
+/// An error returned by a system.
+pub struct SystemError {
+    /// The name of the system that failed.
+    pub system: String,
+    /// The error the system returned.
+    pub error: BoxedError,
+}
+impl Debug for SystemError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SystemError")
+            .field("system", &self.system)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+impl Display for SystemError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "System `{}` failed: {:?}", self.system, self.error)
+    }
+}
+
+/// A function that handles errors from systems. The World calls its handler (see
+/// `World::set_error_handler`) every time a system returns an error.
+pub type ErrorHandler = fn(&mut World, SystemError);
+
+/// The default `ErrorHandler`, which panics with the error.
+pub fn panic(_: &mut World, error: SystemError) {
+    panic!("{error}");
+}
+/// An `ErrorHandler` that prints errors to stderr, along with the name of the system that
+/// failed. This is only available with the `std` feature.
+#[cfg(feature = "std")]
+pub fn log(_: &mut World, error: SystemError) {
+    std::eprintln!("{error}");
+}
+/// An `ErrorHandler` that silently drops errors.
+pub fn ignore(_: &mut World, _: SystemError) {}
+/// An `ErrorHandler` that stores errors in the `SystemErrors` resource. The resource is added to
+/// the World if it doesn't exist yet.
+pub fn collect(world: &mut World, error: SystemError) {
+    if world.get_resource::<SystemErrors>().is_none() {
+        world.insert_resource(SystemErrors::default());
+    }
+    world
+        .get_resource_mut::<SystemErrors>()
+        .unwrap()
+        .0
+        .push(error);
+}
+
+/// A resource storing all the errors systems have returned. This is filled by the `collect`
+/// error handler.
+#[derive(Debug, Default)]
+pub struct SystemErrors(pub Vec<SystemError>);
+
+/// The outputs systems registered in the World may return. Systems returning `()` can't fail;
+/// systems returning a `Result<(), E>` send their errors to the World's `ErrorHandler`. Systems
+/// made with `IntoSystem::run_if` return an `Option`, which is `None` if they didn't run.
+pub trait SystemOutput {
+    /// Handles the output of the system named `system`.
+    fn handle(self, world: &mut World, system: &str);
+}
+impl SystemOutput for () {
+    fn handle(self, _: &mut World, _: &str) {}
+}
+impl<E: Debug + MaybeSend + MaybeSync + 'static> SystemOutput for Result<(), E> {
+    fn handle(self, world: &mut World, system: &str) {
+        if let Err(error) = self {
+            (world.error_handler)(
+                world,
+                SystemError {
+                    system: system.to_string(),
+                    error: Box::new(error),
+                },
+            );
+        }
+    }
+}
+
+impl<O: SystemOutput> SystemOutput for Option<O> {
+    fn handle(self, world: &mut World, system: &str) {
+        if let Some(output) = self {
+            output.handle(world, system);
+        }
+    }
+}
+
+/// Wraps a system, handling its output with `SystemOutput`. The World's `Systems` store systems
+/// in this, so that every system has the same output type.
+pub struct HandledSystem<S: System<In = ()>>(
+    /// The system.
+    pub S,
+    /// Where the system's stats are stored in `SystemDiagnostics`. See `System::stats_idx`.
+    pub Option<usize>,
+)
+where
+    S::Out: SystemOutput;
+impl<S: System<In = ()>> System for HandledSystem<S>
+where
+    S::Out: SystemOutput,
+{
+    type In = ();
+    type Out = ();
+
+    fn execute(&mut self, _: Self::In, world: &mut World) -> Self::Out {
+        self.0.execute((), world).handle(world, self.0.name());
+    }
+    #[cfg(feature = "parallel")]
+    fn prepare(&mut self, _: Self::In, world: &mut World) -> Prepared<Self::Out> {
+        match self.0.prepare((), world) {
+            Prepared::Task => Prepared::Task,
+            Prepared::Done(out) => {
+                out.handle(world, self.0.name());
+                Prepared::Done(())
+            }
+        }
+    }
+    #[cfg(feature = "parallel")]
+    fn run_prepared(&mut self) {
+        self.0.run_prepared();
+    }
+    #[cfg(feature = "parallel")]
+    fn finish(&mut self, world: &mut World) -> Self::Out {
+        self.0.finish(world).handle(world, self.0.name());
+    }
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+    fn access(&self) -> Access {
+        self.0.access()
+    }
+    fn order(&self) -> SystemOrder {
+        self.0.order()
+    }
+    fn stats_idx(&self) -> Option<usize> {
+        self.1
+    }
+    fn set_stats_idx(&mut self, idx: usize) {
+        self.1 = Some(idx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{self as secs, prelude::*},
+    };
+
+    #[derive(Component)]
+    struct Health(i32);
+
+    // The field is only read by the `Debug` impl
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct NegativeHealth(i32);
+
+    fn check_health(query: &Query<&Health>) -> Result<(), NegativeHealth> {
+        for health in query {
+            if health.0 < 0 {
+                return Err(NegativeHealth(health.0));
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies that errors from fallible systems are sent to the World's error handler, along
+    /// with the failing system's name.
+    #[test]
+    fn collect_errors() {
+        let mut world = World::default();
+        world.set_error_handler(collect);
+        world.spawn(Health(10));
+        world.add_system(check_health);
+
+        world.run_once();
+        assert!(world.get_resource::<SystemErrors>().is_none());
+
+        world.spawn(Health(-5));
+        world.run_once();
+
+        let errors = world.get_resource::<SystemErrors>().unwrap();
+        assert_eq!(errors.0.len(), 1);
+        assert!(errors.0[0].system.ends_with("check_health"));
+        assert_eq!(
+            errors.0[0].to_string(),
+            alloc::format!("System `{}` failed: NegativeHealth(-5)", errors.0[0].system)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "NegativeHealth(-1)")]
+    fn panic_by_default() {
+        let mut world = World::default();
+        world.spawn(Health(-1));
+        world.add_system(check_health);
+        world.run_once();
+    }
+
+    /// Verifies that the `log` handler reports errors without stopping the World.
+    #[cfg(feature = "std")]
+    #[test]
+    fn log_errors() {
+        let mut world = World::default();
+        world.set_error_handler(log);
+        world.spawn(Health(-1));
+        world.add_system(check_health);
+        world.run_for(2);
+        assert!(world.get_resource::<SystemErrors>().is_none());
+    }
+}
@@ -0,0 +1,380 @@
+//! Runs systems on multiple threads. This module is only available with the `parallel` feature.
+//!
+//! Before `Systems` run, they're split into batches (see `batches`). A batch is a run of
+//! consecutive systems that can all run at the same time: none of them access the whole World,
+//! none of their accesses conflict (see `Access::is_compatible`), and none of them are ordered
+//! relative to each other. In the `Immediate` command mode, a system that queues commands also
+//! ends its batch, since the systems after it should see its commands. Batches still run one
+//! after another, in order, so running systems in parallel never changes the result of a frame.
+//!
+//! Systems can't take their data from the World on another thread, since that needs mutable
+//! access to the World. Instead, each system in a batch is prepared on the current thread with
+//! `System::prepare`, which takes its data and stores it in the system. The prepared systems are
+//! then moved to the `ThreadPool`'s worker threads, which run them with `System::run_prepared`
+//! and send them back. Once they're all back, they're finished one at a time on the current
+//! thread with `System::finish`, which releases their data and handles their output.
+//!
+//! Some systems can't run on another thread, like piped systems and systems with a `ParamSet`, so
+//! they run on the current thread while they're prepared - while the systems before them in the
+//! batch are already running. So that those systems' queries don't change under them, commands
+//! released during a batch are held in a `CommandBuffer` until the whole batch is done. In the
+//! `Immediate` command mode, only the last system in a batch can queue commands, so they're
+//! still applied before the next system runs.
+//!
+//! The pool's threads are started the first time a batch runs, and are shared by every World.
+//! Since systems are moved to the workers and back, rather than borrowed, the workers don't need
+//! to be scoped to the batch they're running.
+//!
+//! Stepping (see the `stepping` module) runs systems one at a time, on the current thread.
+
+use {
+    crate::{
+        _crate_prelude::*,
+        sync::{Lock, Shared},
+        system::{
+            access::Access,
+            command::{CommandBuffer, CommandMode},
+            diagnostics, order, panic, ErasedSystem, SystemParamFn,
+        },
+    },
+    alloc::{format, string::String},
+    core::{ops::Range, time::Duration},
+    std::{
+        panic::{catch_unwind, AssertUnwindSafe},
+        sync::{
+            mpsc::{self, Receiver, Sender},
+            Mutex, OnceLock,
+        },
+        thread,
+    },
+};
+
+/// A job for the `ThreadPool` to run.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A set of worker threads that run jobs sent to them. There's only one pool, which is shared by
+/// every World and every parallel query; see `ThreadPool::get`.
+pub struct ThreadPool {
+    jobs: Sender<Job>,
+    threads: usize,
+}
+impl ThreadPool {
+    /// The pool. Its threads are started the first time this is called, with one thread for each
+    /// of `std::thread::available_parallelism`.
+    pub fn get() -> &'static Self {
+        static POOL: OnceLock<ThreadPool> = OnceLock::new();
+        POOL.get_or_init(|| {
+            let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+            let (jobs, receiver) = mpsc::channel();
+            let receiver: &'static Mutex<Receiver<Job>> = Box::leak(Box::new(Mutex::new(receiver)));
+            for idx in 0..threads {
+                thread::Builder::new()
+                    .name(format!("secs worker {idx}"))
+                    .spawn(move || loop {
+                        // The lock is released before the job runs, so other workers can take jobs
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Failed to start the thread pool");
+            }
+            Self { jobs, threads }
+        })
+    }
+
+    /// The number of worker threads.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Runs `job` on one of the worker threads. If every worker is busy, the job waits until one
+    /// finishes its current job.
+    ///
+    /// Jobs should catch their own panics, since a panic would stop the worker running it.
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+        self.jobs
+            .send(Box::new(job))
+            .expect("The thread pool's workers stopped");
+    }
+}
+
+/// What `System::prepare` did with a system.
+pub enum Prepared<Out> {
+    /// The system's data was taken from the World, and it's waiting to run with
+    /// `System::run_prepared`.
+    Task,
+    /// The system already ran on the current thread, and returned this output.
+    Done(Out),
+}
+impl<Out> Prepared<Out> {
+    /// Changes the system's output with `map`, if it already ran.
+    pub fn map<Mapped>(self, map: fn(Out) -> Mapped) -> Prepared<Mapped> {
+        match self {
+            Self::Task => Prepared::Task,
+            Self::Done(out) => Prepared::Done(map(out)),
+        }
+    }
+}
+
+/// The data a `SystemStore` took from the World when it was prepared, and its output once it's
+/// run.
+pub struct StoreTask<F: SystemParamFn<Params>, Params> {
+    input: Option<F::In>,
+    data: F::Data,
+    out: Option<F::Out>,
+}
+impl<F: SystemParamFn<Params>, Params> StoreTask<F, Params> {
+    /// Creates a task that calls the function with `input` and `data`.
+    pub fn new(input: F::In, data: F::Data) -> Self {
+        Self {
+            input: Some(input),
+            data,
+            out: None,
+        }
+    }
+    /// Calls `function` with the task in `task`. The task is taken out while the function runs,
+    /// so if it panics, the data is dropped instead of being released into the World.
+    pub fn run(task: &mut Option<Self>, function: &F) {
+        let mut running = task
+            .take()
+            .expect("Tried to run a system that wasn't prepared");
+        let input = running.input.take().expect("Tried to run a task twice");
+        running.out = Some(function.call(input, &mut running.data, None));
+        *task = Some(running);
+    }
+    /// Releases the task's data back into the World, and returns the function's output.
+    pub fn finish(self, world: &mut World, state: &mut F::State) -> F::Out {
+        F::release(self.data, world, state);
+        self.out.expect("Tried to finish a task before running it")
+    }
+}
+
+/// Splits sorted systems into batches that can run in parallel, when commands are applied in
+/// `mode`. See the module docs.
+pub fn batches(systems: &[Box<dyn ErasedSystem>], mode: CommandMode) -> Vec<Range<usize>> {
+    let successors = order::successors(systems);
+    let accesses: Vec<Access> = systems.iter().map(|system| system.access()).collect();
+    let mut batches: Vec<Range<usize>> = Vec::new();
+
+    for (idx, access) in accesses.iter().enumerate() {
+        if let Some(batch) = batches.last_mut() {
+            let fits = !access.world
+                && batch.clone().all(|other| {
+                    let ends_batch = accesses[other].world
+                        || (accesses[other].commands && mode == CommandMode::Immediate);
+                    !ends_batch
+                        && access.is_compatible(&accesses[other])
+                        && !successors[other].contains(&idx)
+                });
+            if fits {
+                batch.end = idx + 1;
+                continue;
+            }
+        }
+        batches.push(idx..idx + 1);
+    }
+
+    batches
+}
+
+/// Runs the systems in `batch` on the `ThreadPool`. Batches with one system just run it on the
+/// current thread, with `diagnostics::run_traced`.
+///
+/// Like `run_traced`, this calls the World's `SystemHook`s and records `SystemDiagnostics`, and
+/// systems that panic are disabled if the World isolates panics (otherwise, the panic is resumed
+/// once every system is back in `systems`). Hooks are called on the current thread, before the
+/// first system in the batch starts and after the last one finishes. Commands the systems queued
+/// are released before the last hooks are called.
+pub fn run_batch(systems: &mut Vec<Box<dyn ErasedSystem>>, batch: Range<usize>, world: &mut World) {
+    if batch.len() == 1 {
+        diagnostics::run_traced(&mut systems[batch.start], world);
+        return;
+    }
+
+    let names: Vec<String> = systems[batch.clone()]
+        .iter()
+        .map(|system| system.name().into())
+        .collect();
+    for name in &names {
+        diagnostics::before_system(name, world);
+    }
+
+    let commands = Shared::new(Lock::new(CommandBuffer::deferred()));
+    let active = world.active_commands.replace(commands.clone());
+    // Systems are taken out of `running` while they're on a worker thread
+    let mut running: Vec<Option<Box<dyn ErasedSystem>>> =
+        systems.drain(batch.clone()).map(Some).collect();
+    let mut panics = Vec::new();
+    let mut durations: Vec<Option<Duration>> = vec![None; running.len()];
+    let (sender, results) = mpsc::channel();
+    let pool = ThreadPool::get();
+    let mut sent = 0;
+    for (idx, slot) in running.iter_mut().enumerate() {
+        let system = slot.as_mut().unwrap();
+        // Systems that can't run on another thread, like piped systems, run while they're prepared
+        let start = world.clock.as_ref().map(|clock| clock.now());
+        let prepared = catch_unwind(AssertUnwindSafe(|| system.prepare((), world)));
+        durations[idx] =
+            start.map(|start| world.clock.as_ref().unwrap().now().saturating_sub(start));
+        match prepared {
+            Ok(Prepared::Task) => {
+                let mut system = slot.take().unwrap();
+                let clock = world.clock.clone();
+                let sender = sender.clone();
+                pool.spawn(move || {
+                    let start = clock.as_ref().map(|clock| clock.now());
+                    let result = catch_unwind(AssertUnwindSafe(|| system.run_prepared()));
+                    let duration =
+                        start.map(|start| clock.as_ref().unwrap().now().saturating_sub(start));
+                    // The batch only stops waiting once every system is sent back
+                    sender
+                        .send((idx, system, result.map(|()| duration)))
+                        .unwrap();
+                });
+                sent += 1;
+            }
+            Ok(Prepared::Done(())) => {}
+            Err(payload) => panics.push((idx, payload)),
+        }
+    }
+
+    let mut finished = Vec::with_capacity(sent);
+    for (idx, system, result) in results.iter().take(sent) {
+        running[idx] = Some(system);
+        finished.push((idx, result));
+    }
+    // Systems finish in the order they were added, not the order they came back in
+    finished.sort_unstable_by_key(|(idx, _)| *idx);
+    for (idx, result) in finished {
+        match result {
+            Ok(duration) => {
+                if let (Some(total), Some(duration)) = (&mut durations[idx], duration) {
+                    *total += duration;
+                }
+                let system = running[idx].as_mut().unwrap();
+                if let Err(payload) = catch_unwind(AssertUnwindSafe(|| system.finish(world))) {
+                    panics.push((idx, payload));
+                }
+            }
+            // The system's data was dropped while the panic unwound
+            Err(payload) => panics.push((idx, payload)),
+        }
+    }
+
+    systems.splice(
+        batch.start..batch.start,
+        running.into_iter().map(Option::unwrap),
+    );
+    world.active_commands = active;
+    commands.borrow_mut().release(world);
+    let systems = &mut systems[batch];
+    for (idx, payload) in panics {
+        panic::disable(&mut systems[idx], payload, world);
+    }
+    for (system, duration) in systems.iter_mut().zip(durations) {
+        diagnostics::after_system(&mut **system, duration, world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{self as secs, prelude::*},
+    };
+
+    struct Gravity(f32);
+    struct Wind(f32);
+
+    fn read_gravity(_: &Resource<Gravity>) {}
+    fn read_both(_: &Resource<Gravity>, _: &Resource<Wind>) {}
+    fn write_wind(wind: &mut Resource<Wind>) {
+        wind.get_mut().0 += 1.0;
+    }
+    fn write_gravity(gravity: &mut Resource<Gravity>) {
+        gravity.get_mut().0 *= 2.0;
+    }
+
+    /// Verifies that systems are batched by their access and ordering, and that batches run.
+    #[test]
+    fn batching() {
+        let mut world = World::default();
+        world.insert_resource(Gravity(1.0));
+        world.insert_resource(Wind(0.0));
+        world.add_system(read_gravity);
+        world.add_system(write_wind);
+        world.add_system(write_gravity);
+        world.add_system(read_both.after("write_gravity"));
+        world.add_system(read_gravity);
+        world.add_system(apply_deferred);
+
+        world.run_once();
+        world.run_once();
+        assert_eq!(world.get_resource::<Gravity>().unwrap().0, 4.0);
+        assert_eq!(world.get_resource::<Wind>().unwrap().0, 2.0);
+
+        let systems = &world.systems.borrow().systems;
+        assert_eq!(
+            batches(systems, CommandMode::Deferred),
+            [0..2, 2..3, 3..5, 5..6]
+        );
+    }
+
+    #[derive(Component)]
+    struct Position(i32);
+
+    fn slow_sum(positions: &Query<&Position>, sums: &mut Resource<Vec<i32>>) {
+        thread::sleep(Duration::from_millis(20));
+        let sum = positions.iter().map(|position| position.0).sum();
+        sums.get_mut().push(sum);
+    }
+    fn despawn_first(_: In<()>, commands: &mut CommandQueue) {
+        commands.despawn(0);
+    }
+
+    /// Verifies that a system that runs while it's prepared doesn't apply its commands until
+    /// the rest of its batch is done.
+    #[test]
+    fn batch_commands() {
+        let mut world = World::default();
+        world.insert_resource(Vec::<i32>::new());
+        world.spawn(Position(1));
+        world.spawn(Position(2));
+        world.add_system(slow_sum);
+        world.add_system((|| {}).pipe(despawn_first));
+        world.run_once();
+        world.run_once();
+
+        assert_eq!(*world.get_resource::<Vec<i32>>().unwrap(), [3, 2]);
+    }
+
+    struct Threads(Mutex<Vec<String>>);
+
+    fn record_thread(threads: &Resource<Threads>) {
+        let name = thread::current().name().unwrap_or_default().into();
+        threads.get().0.lock().unwrap().push(name);
+    }
+
+    /// Verifies that batched systems run on the thread pool's workers, and are put back in order
+    /// afterwards.
+    #[test]
+    fn thread_pool() {
+        let mut world = World::default();
+        world.insert_resource(Threads(Mutex::new(Vec::new())));
+        world.add_system(record_thread.label("first"));
+        world.add_system(record_thread.label("second"));
+
+        world.run_once();
+        world.run_once();
+        let threads = world.get_resource::<Threads>().unwrap();
+        let threads = threads.0.lock().unwrap();
+        assert_eq!(threads.len(), 4);
+        assert!(threads.iter().all(|name| name.starts_with("secs worker")));
+
+        let systems = &world.systems.borrow().systems;
+        let labels: Vec<_> = systems.iter().map(|system| system.order().labels).collect();
+        assert_eq!(labels, [["first"], ["second"]]);
+    }
+}
@@ -0,0 +1,61 @@
+use {
+    crate::{_crate_prelude::*, system::WorldData},
+    core::ops::{Deref, DerefMut},
+};
+
+/// A value that's local to a single system. The value persists between runs of that system,
+/// but other systems can't see it - even if they also use a `Local` of the same type.
+///
+/// The value starts out as `T::default()`.
+#[derive(Debug, Default)]
+pub struct Local<T: Default + 'static>(T);
+
+/// Allows `Local`s to be used as system parameters. The local value is moved out of the system's
+/// state while the system runs, then moved back once it finishes.
+impl<T: Default + 'static> WorldData for Local<T> {
+    type State = T;
+
+    fn take(_: &mut World, state: &mut Self::State) -> Self {
+        Self(core::mem::take(state))
+    }
+
+    fn release(self, _: &mut World, state: &mut Self::State) {
+        *state = self.0;
+    }
+}
+impl<T: Default + 'static> Deref for Local<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl<T: Default + 'static> DerefMut for Local<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{_crate_prelude::*, prelude::*};
+
+    fn count(counter: &mut Local<u32>, runs: &mut Resource<Vec<u32>>) {
+        **counter += 1;
+        runs.get_mut().push(**counter);
+    }
+
+    /// Verifies that a `Local` keeps its value between runs, and that each system gets its own.
+    #[test]
+    fn local_is_per_system() {
+        let mut world = World::default();
+        world.insert_resource(Vec::<u32>::new());
+        world.add_system(count);
+        world.add_system(count);
+
+        world.run_once();
+        world.run_once();
+
+        assert_eq!(*world.get_resource::<Vec<u32>>().unwrap(), [1, 1, 2, 2]);
+    }
+}
@@ -0,0 +1,340 @@
+//! Explicit ordering between systems, and detecting systems that need it.
+//!
+//! By default, systems run in the order they're added to the World. That order is easy to break
+//! by accident, though - for example, by adding plugins in a different order. Systems can
+//! instead declare their order with `IntoSystem::before` and `IntoSystem::after`, which take the
+//! name of another system (see `name_matches`) or a label added with `IntoSystem::label`.
+//! `Systems` sorts its systems to follow these constraints before it runs them. Systems without
+//! any constraints between them keep the order they were added in.
+//!
+//! `Systems::ambiguities` finds pairs of systems that access the same data, where at least one
+//! of them accesses it mutably, but which aren't ordered relative to each other. The result of
+//! running those systems depends on the order they were added in, which is usually a bug.
+//! Exclusive systems access the whole World, so they're ambiguous with every unordered system
+//! that accesses any data - except for sync points like `apply_deferred`, which only apply
+//! commands.
+
+use {
+    crate::{
+        _crate_prelude::*,
+        system::{
+            access::{Access, DataAccess},
+            command::apply_deferred,
+            ErasedSystem, System,
+        },
+    },
+    alloc::string::{String, ToString},
+    core::fmt::{self, Display, Formatter},
+};
+
+/// Checks if a system's name matches `name`. System names are full type paths, so this matches
+/// either the whole path (`my_game::player::move_player`) or just the last part of it
+/// (`move_player`).
+pub fn name_matches(system: &str, name: &str) -> bool {
+    system == name
+        || system
+            .strip_suffix(name)
+            .is_some_and(|path| path.ends_with("::"))
+}
+
+/// The ordering constraints for a system. See the module docs.
+#[derive(Clone, Debug, Default)]
+pub struct SystemOrder {
+    /// Labels that other systems can use to refer to this system, besides its name.
+    pub labels: Vec<&'static str>,
+    /// Names or labels of systems this system must run before.
+    pub before: Vec<&'static str>,
+    /// Names or labels of systems this system must run after.
+    pub after: Vec<&'static str>,
+}
+impl SystemOrder {
+    /// Adds all the constraints from `other` to this order.
+    pub fn extend(&mut self, other: SystemOrder) {
+        self.labels.extend(other.labels);
+        self.before.extend(other.before);
+        self.after.extend(other.after);
+    }
+
+    /// If a system with this order and the name `system` is referred to by `name`.
+    pub fn is_named(&self, system: &str, name: &str) -> bool {
+        name_matches(system, name) || self.labels.contains(&name)
+    }
+}
+
+/// A system with ordering constraints. These are made with `IntoSystem::before`,
+/// `IntoSystem::after`, and `IntoSystem::label`.
+pub struct OrderedSystem<S: System> {
+    system: S,
+    order: SystemOrder,
+}
+impl<S: System> OrderedSystem<S> {
+    /// Adds ordering constraints to `system`.
+    pub fn new(system: S, order: SystemOrder) -> Self {
+        Self { system, order }
+    }
+}
+impl<S: System> System for OrderedSystem<S> {
+    type In = S::In;
+    type Out = S::Out;
+
+    fn execute(&mut self, input: Self::In, world: &mut World) -> Self::Out {
+        self.system.execute(input, world)
+    }
+    #[cfg(feature = "parallel")]
+    fn prepare(
+        &mut self,
+        input: Self::In,
+        world: &mut World,
+    ) -> crate::system::executor::Prepared<Self::Out> {
+        self.system.prepare(input, world)
+    }
+    #[cfg(feature = "parallel")]
+    fn run_prepared(&mut self) {
+        self.system.run_prepared();
+    }
+    #[cfg(feature = "parallel")]
+    fn finish(&mut self, world: &mut World) -> Self::Out {
+        self.system.finish(world)
+    }
+    fn name(&self) -> &str {
+        self.system.name()
+    }
+    fn access(&self) -> Access {
+        self.system.access()
+    }
+    fn order(&self) -> SystemOrder {
+        let mut order = self.system.order();
+        order.extend(self.order.clone());
+        order
+    }
+}
+
+/// Two systems that access the same data, with at least one accessing it mutably, but without
+/// any ordering between them. See `Systems::ambiguities`.
+#[derive(Clone, Debug)]
+pub struct Ambiguity {
+    /// The name of the system that was added first.
+    pub first: String,
+    /// The name of the system that was added second.
+    pub second: String,
+    /// The data both systems access.
+    pub data: DataAccess,
+}
+impl Display for Ambiguity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Systems `{}` and `{}` both access the {} `{}`, and at least one accesses it \
+            mutably, but they aren't ordered with `before` or `after`.",
+            self.first, self.second, self.data.kind, self.data.name
+        )
+    }
+}
+
+type BoxedSystem = Box<dyn ErasedSystem>;
+
+/// Finds the systems that must run after each system, from the systems' ordering constraints.
+pub fn successors(systems: &[BoxedSystem]) -> Vec<Vec<usize>> {
+    let orders: Vec<SystemOrder> = systems.iter().map(|system| system.order()).collect();
+    let mut successors = vec![Vec::new(); systems.len()];
+
+    for (idx, order) in orders.iter().enumerate() {
+        for (other, other_order) in orders.iter().enumerate() {
+            if other == idx {
+                continue;
+            }
+            let other_name = systems[other].name();
+            if order
+                .before
+                .iter()
+                .any(|name| other_order.is_named(other_name, name))
+            {
+                successors[idx].push(other);
+            }
+            if order
+                .after
+                .iter()
+                .any(|name| other_order.is_named(other_name, name))
+            {
+                successors[other].push(idx);
+            }
+        }
+    }
+
+    successors
+}
+
+/// Sorts systems so they follow their ordering constraints. Systems that aren't ordered relative
+/// to each other stay in the order they were added.
+///
+/// Panics if the constraints form a cycle.
+pub fn sort(systems: &mut Vec<BoxedSystem>) {
+    let successors = successors(systems);
+    let mut predecessors = vec![0_usize; systems.len()];
+    for &next in successors.iter().flatten() {
+        predecessors[next] += 1;
+    }
+
+    let mut sorted = Vec::with_capacity(systems.len());
+    let mut done = vec![false; systems.len()];
+    while sorted.len() < systems.len() {
+        let Some(next) = (0..systems.len()).find(|&idx| !done[idx] && predecessors[idx] == 0)
+        else {
+            let cycle: Vec<&str> = (0..systems.len())
+                .filter(|&idx| !done[idx])
+                .map(|idx| systems[idx].name())
+                .collect();
+            panic!(
+                "The ordering constraints between these systems form a cycle: {}",
+                cycle.join(", ")
+            );
+        };
+        done[next] = true;
+        sorted.push(next);
+        for &after in &successors[next] {
+            predecessors[after] -= 1;
+        }
+    }
+
+    let mut unsorted: Vec<Option<BoxedSystem>> = systems.drain(..).map(Some).collect();
+    systems.extend(sorted.into_iter().map(|idx| unsorted[idx].take().unwrap()));
+}
+
+/// Checks if a system is a sync point like `apply_deferred`. Sync points are exclusive, but
+/// they only apply commands, so where they run between unordered systems doesn't change what
+/// those systems see of each other.
+fn is_sync_point(system: &dyn ErasedSystem) -> bool {
+    system.name() == core::any::type_name_of_val(&apply_deferred)
+}
+
+/// Finds every pair of systems that access the same data in conflicting ways without being
+/// ordered. Exclusive systems never run at the same time as other systems, but the order they
+/// run in still isn't deterministic, so they conflict with any system that accesses data. Only
+/// sync points (see `is_sync_point`) are skipped.
+pub fn ambiguities(systems: &[BoxedSystem]) -> Vec<Ambiguity> {
+    let successors = successors(systems);
+    // `reachable[a][b]` is true if `a` has to run before `b`, directly or through other systems
+    let reachable: Vec<Vec<bool>> = (0..systems.len())
+        .map(|start| {
+            let mut reached = vec![false; systems.len()];
+            let mut stack = successors[start].clone();
+            while let Some(idx) = stack.pop() {
+                if !reached[idx] {
+                    reached[idx] = true;
+                    stack.extend_from_slice(&successors[idx]);
+                }
+            }
+            reached
+        })
+        .collect();
+    let accesses: Vec<Access> = systems.iter().map(|system| system.access()).collect();
+
+    let mut ambiguities = Vec::new();
+    for first in 0..systems.len() {
+        for second in first + 1..systems.len() {
+            if is_sync_point(&*systems[first])
+                || is_sync_point(&*systems[second])
+                || reachable[first][second]
+                || reachable[second][first]
+            {
+                continue;
+            }
+            let conflict = match (accesses[first].world, accesses[second].world) {
+                (false, false) => accesses[first].first_conflict(&accesses[second]),
+                (true, false) => accesses[second].data.first().copied(),
+                (false, true) => accesses[first].data.first().copied(),
+                (true, true) => Some(DataAccess::world()),
+            };
+            if let Some(data) = conflict {
+                ambiguities.push(Ambiguity {
+                    first: systems[first].name().to_string(),
+                    second: systems[second].name().to_string(),
+                    data,
+                });
+            }
+        }
+    }
+
+    ambiguities
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{_crate_prelude::*, prelude::*};
+
+    struct Score(u32);
+
+    fn add_points(score: &mut Resource<Score>) {
+        score.get_mut().0 += 10;
+    }
+    fn double_points(score: &mut Resource<Score>) {
+        score.get_mut().0 *= 2;
+    }
+    fn read_points(log: &mut Resource<Vec<u32>>, score: &Resource<Score>) {
+        log.get_mut().push(score.get().0);
+    }
+
+    /// Verifies that systems are sorted by their constraints.
+    #[test]
+    fn ordering() {
+        let mut world = World::default();
+        world.insert_resource(Score(0));
+        world.insert_resource(Vec::<u32>::new());
+        world.add_system(read_points.label("log"));
+        world.add_system(double_points.after("add_points").before("log"));
+        world.add_system(add_points);
+
+        world.run_once();
+        assert_eq!(*world.get_resource::<Vec<u32>>().unwrap(), [20]);
+        assert!(world.ambiguities().is_empty());
+    }
+
+    /// Verifies that unordered systems accessing the same data are reported.
+    #[test]
+    fn ambiguities() {
+        let mut world = World::default();
+        world.add_system(add_points);
+        world.add_system(double_points);
+        world.add_system(apply_deferred);
+
+        let ambiguities = world.ambiguities();
+        assert_eq!(ambiguities.len(), 1);
+        assert!(ambiguities[0].first.ends_with("add_points"));
+        assert!(ambiguities[0].second.ends_with("double_points"));
+        assert!(ambiguities[0].data.name.ends_with("Score"));
+    }
+
+    fn reset_points(world: &mut World) {
+        world.get_resource_mut::<Score>().unwrap().0 = 0;
+    }
+
+    /// Verifies that exclusive systems are ambiguous with unordered systems that access data,
+    /// unless they're ordered.
+    #[test]
+    fn exclusive_ambiguities() {
+        let mut world = World::default();
+        world.add_system(reset_points);
+        world.add_system(add_points);
+        world.add_system(double_points.after("reset_points"));
+
+        let ambiguities = world.ambiguities();
+        let pairs: Vec<(&str, &str)> = ambiguities
+            .iter()
+            .map(|ambiguity| (ambiguity.first.as_str(), ambiguity.second.as_str()))
+            .collect();
+        assert_eq!(pairs.len(), 2);
+        assert!(pairs[0].0.ends_with("reset_points") && pairs[0].1.ends_with("add_points"));
+        assert!(pairs[1].0.ends_with("add_points") && pairs[1].1.ends_with("double_points"));
+        assert!(ambiguities[0].data.name.ends_with("Score"));
+    }
+
+    #[test]
+    #[should_panic(expected = "form a cycle")]
+    fn cycles() {
+        let mut world = World::default();
+        world.insert_resource(Score(0));
+        world.add_system(add_points.before("double_points"));
+        world.add_system(double_points.before("add_points"));
+        world.run_once();
+    }
+}
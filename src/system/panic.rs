@@ -0,0 +1,203 @@
+//! Isolates systems that panic, so one buggy system can't take down the whole World. This module
+//! is only available with the `std` feature.
+//!
+//! Isolation is opt-in: once `World::set_isolate_panics(true)` is called, every system the
+//! World's `Systems` run, and every one-shot system run with `World::run_system`, is run under
+//! `std::panic::catch_unwind`. If a system panics, any data it took from the World is dropped as
+//! the panic unwinds, which releases its borrows; commands it queued are thrown away. The panic
+//! is recorded in the `SystemPanics` resource, the system is replaced with a `DisabledSystem`,
+//! and the rest of the frame continues as normal. Systems run with `World::run_system_once`
+//! aren't isolated, since they're run directly rather than stored in the World.
+//!
+//! Note that the error handler is called inside the system, so while panics are isolated, the
+//! default `error::panic` handler disables the system that returned an error instead of
+//! crashing.
+
+use {
+    crate::{
+        _crate_prelude::*,
+        system::{access::Access, order::SystemOrder, ErasedSystem, System},
+    },
+    alloc::string::{String, ToString},
+    core::any::Any,
+    std::panic::{self, AssertUnwindSafe},
+};
+
+/// A panic caught while a system was running.
+#[derive(Clone, Debug)]
+pub struct SystemPanic {
+    /// The name of the system that panicked.
+    pub system: String,
+    /// The panic's message, if it had one.
+    pub message: Option<String>,
+}
+
+/// A resource storing every panic caught from a system. The resource is added to the World the
+/// first time a system panics.
+#[derive(Debug, Default)]
+pub struct SystemPanics(pub Vec<SystemPanic>);
+
+/// A system that replaced one that panicked. It does nothing when it runs, but keeps the old
+/// system's name, access, order, and stats.
+pub struct DisabledSystem {
+    name: String,
+    access: Access,
+    order: SystemOrder,
+    stats_idx: Option<usize>,
+}
+impl System for DisabledSystem {
+    type In = ();
+    type Out = ();
+
+    fn execute(&mut self, _: Self::In, _: &mut World) -> Self::Out {}
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn access(&self) -> Access {
+        self.access.clone()
+    }
+    fn order(&self) -> SystemOrder {
+        self.order.clone()
+    }
+    fn stats_idx(&self) -> Option<usize> {
+        self.stats_idx
+    }
+    fn set_stats_idx(&mut self, idx: usize) {
+        self.stats_idx = Some(idx);
+    }
+}
+
+/// Runs `run`, catching any panic if the World isolates panics. Panics aren't caught otherwise.
+///
+/// If a panic is caught, the World's active command buffer is put back, in case the panic
+/// happened while something (like a `ParamSet`) had swapped it out.
+pub fn catch(world: &mut World, run: impl FnOnce(&mut World)) -> Result<(), Box<dyn Any + Send>> {
+    if world.isolate_panics {
+        let active = world.active_commands.clone();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run(world)));
+        if result.is_err() {
+            world.active_commands = active;
+        }
+        result
+    } else {
+        run(world);
+        Ok(())
+    }
+}
+
+/// Runs a system. If the World isolates panics and the system panics, the panic is added to the
+/// `SystemPanics` resource and the system is replaced with a `DisabledSystem`.
+pub fn run_isolated(system: &mut Box<dyn ErasedSystem>, world: &mut World) {
+    if let Err(payload) = catch(world, |world| system.execute((), world)) {
+        disable(system, payload, world);
+    }
+}
+
+/// Handles a panic caught from `system` (for example, on another thread). If the World isolates
+/// panics, the panic is recorded and the system is replaced with a `DisabledSystem`; otherwise,
+/// the panic is resumed.
+pub fn disable(
+    system: &mut Box<dyn ErasedSystem>,
+    payload: Box<dyn Any + Send>,
+    world: &mut World,
+) {
+    if !world.isolate_panics {
+        panic::resume_unwind(payload);
+    }
+    *system = Box::new(record(&**system, payload, world));
+}
+
+/// Records a panic caught from `system` in the `SystemPanics` resource, and returns the
+/// `DisabledSystem` to replace it with.
+pub fn record<S: System + ?Sized>(
+    system: &S,
+    payload: Box<dyn Any + Send>,
+    world: &mut World,
+) -> DisabledSystem {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned());
+    let name = system.name().to_string();
+
+    if world.get_resource::<SystemPanics>().is_none() {
+        world.insert_resource(SystemPanics::default());
+    }
+    world
+        .get_resource_mut::<SystemPanics>()
+        .unwrap()
+        .0
+        .push(SystemPanic {
+            system: name.clone(),
+            message,
+        });
+
+    DisabledSystem {
+        name,
+        access: system.access(),
+        order: system.order(),
+        stats_idx: system.stats_idx(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::prelude::*};
+
+    fn buggy(count: &Resource<u32>) {
+        if *count.get() >= 1 {
+            panic!("count got too high");
+        }
+    }
+
+    fn increment(count: &mut Resource<u32>) {
+        *count.get_mut() += 1;
+    }
+
+    /// Verifies that a panicking system is reported and disabled, and doesn't stop the rest of
+    /// the frame.
+    #[test]
+    fn isolate_panics() {
+        let mut world = World::default();
+        world.set_isolate_panics(true);
+        world.insert_resource(0_u32);
+        world.add_system(buggy);
+        world.add_system(increment);
+
+        world.run_once();
+        world.run_once();
+        world.run_once();
+
+        assert_eq!(*world.get_resource::<u32>().unwrap(), 3);
+        let panics = world.get_resource::<SystemPanics>().unwrap();
+        assert_eq!(panics.0.len(), 1);
+        assert!(panics.0[0].system.ends_with("buggy"));
+        assert_eq!(panics.0[0].message.as_deref(), Some("count got too high"));
+    }
+
+    /// Verifies that one-shot systems are isolated too.
+    #[test]
+    fn isolate_one_shot_panics() {
+        let mut world = World::default();
+        world.set_isolate_panics(true);
+        world.insert_resource(1_u32);
+        let buggy = world.register_system(buggy);
+
+        world.run_system(buggy);
+        world.run_system(buggy);
+
+        let panics = world.get_resource::<SystemPanics>().unwrap();
+        assert_eq!(panics.0.len(), 1);
+        assert!(panics.0[0].system.ends_with("buggy"));
+    }
+
+    /// Verifies that panics aren't caught unless the World isolates them.
+    #[test]
+    #[should_panic(expected = "count got too high")]
+    fn panics_by_default() {
+        let mut world = World::default();
+        world.insert_resource(1_u32);
+        world.add_system(buggy);
+        world.run_once();
+    }
+}
@@ -0,0 +1,371 @@
+use {
+    crate::{
+        _crate_prelude::*,
+        sync::{Lock, Shared},
+        system::{access::Access, command::CommandBuffer, SystemParam, WorldData},
+    },
+    core::mem,
+};
+
+/// A set of system parameters that would conflict if they were used at the same time - for
+/// example, `Query<&mut Transform>` and `Query<&Transform>`. Only one parameter in the set can
+/// be used at a time.
+///
+/// The set is a tuple of parameters, which are accessed with the `p0`, `p1`, `p2`... methods.
+/// Each parameter's data is only taken from the World when its method is called, and the
+/// parameter that was used before it is released first, so only one of them is ever taken at
+/// once. Since those methods borrow the set mutably, Rust won't let two of the parameters be used
+/// at the same time either.
+///
+/// ```rs
+/// fn system(mut set: ParamSet<(Query<&mut Transform>, Query<&Transform>)>) {
+///     for mut transform in set.p0() {
+///         transform.x += 1;
+///     }
+///     for transform in set.p1() {
+///         println!("{}", transform.x);
+///     }
+/// }
+/// ```
+///
+/// A parameter that's released doesn't apply its commands straight away, though: commands it
+/// queued (like a `CommandQueue`'s) are held in the set until the system finishes, and are then
+/// applied like any other parameter's. Applying them while the system runs could move components
+/// that the system's other parameters are still using.
+///
+/// Taking parameters needs the World while the system runs, so systems with a `ParamSet` always
+/// run on the World's thread (see `SystemParam::NEEDS_WORLD`), and a system can only have one
+/// `ParamSet` - adding a system with two is an `AccessConflict`.
+///
+/// The parameters are named with elided lifetimes (like the `&Transform` above), so they aren't
+/// `WorldData` inside the system, only when the set is fetched; the set keeps a function from
+/// then to take them, and the parameters' states as `Any`.
+pub struct ParamSet<'w, T: ParamSetParams> {
+    world: &'w mut World,
+    taken: &'w mut T::Taken,
+    state: &'w mut dyn Any,
+    commands: &'w Shared<Lock<CommandBuffer>>,
+    take: fn(usize, &mut World, &mut T::Taken, &mut dyn Any),
+}
+
+/// The parameters in a `ParamSet`: a tuple of up to 8 parameters.
+pub trait ParamSetParams {
+    /// Each parameter, if it's currently taken from the World.
+    type Taken: Default;
+}
+
+/// `ParamSetParams` whose parameters are all `WorldData`, so they can be taken from the World.
+pub trait ParamSetWorldData: ParamSetParams + 'static {
+    /// Each parameter's state. See `WorldData::State`.
+    type State: Default + 'static;
+
+    /// Releases whichever parameter is currently taken, then takes the one at `index`.
+    fn take_param(
+        index: usize,
+        taken: &mut Self::Taken,
+        world: &mut World,
+        state: &mut Self::State,
+    );
+    /// Releases whichever parameter is currently taken.
+    fn release_taken(taken: &mut Self::Taken, world: &mut World, state: &mut Self::State);
+    /// The parameters in a set are never used at the same time, so they can't conflict with
+    /// each other - but they can still conflict with the system's other parameters.
+    fn access(access: &mut Access);
+}
+
+/// The data a `ParamSet` stores between its system's parameters being taken and released: the
+/// parameter that's currently taken, every parameter's state, and the commands from parameters
+/// that were released while the system ran.
+pub struct ParamSetData<T: ParamSetWorldData> {
+    taken: T::Taken,
+    state: T::State,
+    commands: Shared<Lock<CommandBuffer>>,
+}
+/// Nothing is taken when the system starts. The parameters' states are moved in here while the
+/// system runs, so the set can take parameters without the system's state.
+impl<T: ParamSetWorldData> WorldData for ParamSetData<T> {
+    type State = T::State;
+
+    fn take(_: &mut World, state: &mut Self::State) -> Self {
+        Self {
+            taken: T::Taken::default(),
+            state: mem::take(state),
+            commands: Shared::new(Lock::new(CommandBuffer::deferred())),
+        }
+    }
+
+    /// The last parameter's commands are added to the set's buffer too, so every command is
+    /// released in the order it was queued.
+    fn release(mut self, world: &mut World, state: &mut Self::State) {
+        let active = world.active_commands.replace(self.commands.clone());
+        T::release_taken(&mut self.taken, world, &mut self.state);
+        world.active_commands = active;
+        self.commands.borrow_mut().release(world);
+        *state = self.state;
+    }
+
+    fn access(access: &mut Access) {
+        T::access(access);
+    }
+}
+
+/// Allow `ParamSet`s to be used as system parameters.
+impl<T: ParamSetWorldData> SystemParam for ParamSet<'_, T> {
+    type Data = ParamSetData<T>;
+    type Fetch<'a> = ParamSet<'a, T>;
+    const NEEDS_WORLD: bool = true;
+
+    fn fetch<'a>(data: &'a mut Self::Data, world: &mut Option<&'a mut World>) -> Self::Fetch<'a> {
+        ParamSet {
+            world: world
+                .take()
+                .expect("A ParamSet needs the World, and a system can only have one ParamSet"),
+            taken: &mut data.taken,
+            state: &mut data.state,
+            commands: &data.commands,
+            take: |index, world, taken, state| {
+                T::take_param(index, taken, world, state.downcast_mut().unwrap())
+            },
+        }
+    }
+
+    fn access(access: &mut Access) {
+        access.take_world();
+        T::access(access);
+    }
+}
+
+macro_rules! impl_param_set {
+    ($(($param:ident, $idx:tt, $method:ident)),*) => {
+        impl<$($param,)*> ParamSetParams for ($($param,)*) {
+            type Taken = ($(Option<$param>,)*);
+        }
+
+        impl<$($param: WorldData,)*> ParamSetWorldData for ($($param,)*) {
+            type State = ($($param::State,)*);
+
+            fn take_param(
+                index: usize,
+                taken: &mut Self::Taken,
+                world: &mut World,
+                state: &mut Self::State,
+            ) {
+                Self::release_taken(taken, world, state);
+                match index {
+                    $($idx => taken.$idx = Some($param::take(world, &mut state.$idx)),)*
+                    _ => unreachable!(),
+                }
+            }
+
+            fn release_taken(taken: &mut Self::Taken, world: &mut World, state: &mut Self::State) {
+                $(
+                    if let Some(param) = taken.$idx.take() {
+                        param.release(world, &mut state.$idx);
+                    }
+                )*
+            }
+
+            fn access(access: &mut Access) {
+                let mut set_access = Access::default();
+                $(
+                    let mut param_access = Access::default();
+                    $param::access_mut(&mut param_access);
+                    set_access.extend_unchecked(&param_access);
+                )*
+                access.extend(&set_access);
+            }
+        }
+
+        impl<$($param,)*> ParamSet<'_, ($($param,)*)> {
+            $(
+                /// Borrow one of the parameters in this set. If another parameter in the set
+                /// was being used, it's released first, and its commands are held until the
+                /// system finishes.
+                pub fn $method(&mut self) -> &mut $param {
+                    if self.taken.$idx.is_none() {
+                        let active = self.world.active_commands.replace(self.commands.clone());
+                        (self.take)($idx, self.world, self.taken, self.state);
+                        self.world.active_commands = active;
+                    }
+                    self.taken.$idx.as_mut().unwrap()
+                }
+            )*
+        }
+    };
+}
+impl_param_set!((P0, 0, p0));
+impl_param_set!((P0, 0, p0), (P1, 1, p1));
+impl_param_set!((P0, 0, p0), (P1, 1, p1), (P2, 2, p2));
+impl_param_set!((P0, 0, p0), (P1, 1, p1), (P2, 2, p2), (P3, 3, p3));
+impl_param_set!(
+    (P0, 0, p0),
+    (P1, 1, p1),
+    (P2, 2, p2),
+    (P3, 3, p3),
+    (P4, 4, p4)
+);
+impl_param_set!(
+    (P0, 0, p0),
+    (P1, 1, p1),
+    (P2, 2, p2),
+    (P3, 3, p3),
+    (P4, 4, p4),
+    (P5, 5, p5)
+);
+impl_param_set!(
+    (P0, 0, p0),
+    (P1, 1, p1),
+    (P2, 2, p2),
+    (P3, 3, p3),
+    (P4, 4, p4),
+    (P5, 5, p5),
+    (P6, 6, p6)
+);
+impl_param_set!(
+    (P0, 0, p0),
+    (P1, 1, p1),
+    (P2, 2, p2),
+    (P3, 3, p3),
+    (P4, 4, p4),
+    (P5, 5, p5),
+    (P6, 6, p6),
+    (P7, 7, p7)
+);
+
+#[cfg(test)]
+mod tests {
+    use {
+        crate::{
+            self as secs,
+            _crate_prelude::*,
+            prelude::*,
+            sync::{self, AnyData, Lock, Shared},
+            system::{access::Access, WorldData},
+        },
+        alloc::string::ToString,
+    };
+
+    #[derive(Component)]
+    struct Position(i32);
+
+    fn move_and_sum(
+        mut set: ParamSet<(Query<&mut Position>, Query<&Position>)>,
+        sums: &mut Resource<Vec<i32>>,
+    ) {
+        for mut position in set.p0() {
+            position.0 += 1;
+        }
+        let sum = set.p1().iter().map(|position| position.0).sum();
+        sums.get_mut().push(sum);
+    }
+
+    /// Verifies that conflicting parameters in a `ParamSet` can be used one after another.
+    #[test]
+    fn conflicting_queries() {
+        let mut world = World::default();
+        world.insert_resource(Vec::<i32>::new());
+        world.spawn(Position(1));
+        world.spawn(Position(2));
+        world.add_system(move_and_sum);
+
+        world.run_once();
+        world.run_once();
+
+        assert_eq!(*world.get_resource::<Vec<i32>>().unwrap(), [5, 7]);
+    }
+
+    struct Counter(u32);
+
+    /// Moves the `Counter` resource out of the World while it's taken, so taking it twice at
+    /// once panics.
+    struct TakeCounter(Shared<Lock<AnyData>>);
+    impl WorldData for TakeCounter {
+        type State = ();
+
+        fn take(world: &mut World, _: &mut Self::State) -> Self {
+            let counter = world.storage.resources.remove(&TypeId::of::<Counter>());
+            Self(counter.expect("The counter was already taken"))
+        }
+
+        fn release(self, world: &mut World, _: &mut Self::State) {
+            world
+                .storage
+                .resources
+                .insert(TypeId::of::<Counter>(), self.0);
+        }
+
+        fn access_mut(access: &mut Access) {
+            access.write_resource::<Counter>();
+        }
+    }
+    impl TakeCounter {
+        fn add(&mut self, amount: u32) {
+            sync::downcast_mut::<Counter>(&self.0).0 += amount;
+        }
+    }
+
+    fn count(mut set: ParamSet<(TakeCounter, TakeCounter)>) {
+        set.p0().add(1);
+        set.p1().add(10);
+        set.p0().add(100);
+    }
+
+    /// Verifies that each parameter in a set is only taken when it's used, and that the previous
+    /// one is released first.
+    #[test]
+    fn lazy_take() {
+        let mut world = World::default();
+        world.insert_resource(Counter(0));
+        world.add_system(count);
+        world.run_once();
+
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 111);
+    }
+
+    #[derive(Component)]
+    struct Velocity;
+
+    fn despawn_then_read(
+        positions: &Query<&Position>,
+        mut set: ParamSet<(CommandQueue, Query<&Velocity>)>,
+        sums: &mut Resource<Vec<i32>>,
+    ) {
+        set.p0().despawn(0);
+        set.p1();
+        sums.get_mut()
+            .push(positions.iter().map(|position| position.0).sum());
+    }
+
+    /// Verifies that commands from a parameter that's released aren't applied until the system
+    /// finishes, so the system's other parameters don't see entities move.
+    #[test]
+    fn commands_wait_for_system() {
+        let mut world = World::default();
+        world.insert_resource(Vec::<i32>::new());
+        world.spawn((Position(1), Velocity));
+        world.spawn((Position(2), Velocity));
+        world.add_system(despawn_then_read);
+        world.run_once();
+
+        assert_eq!(*world.get_resource::<Vec<i32>>().unwrap(), [3]);
+        let remaining: usize = world.run_system_once(|query: &Query<&Position>| query.len());
+        assert_eq!(remaining, 1);
+    }
+
+    #[derive(SystemParam)]
+    struct WithSet<'a> {
+        _set: ParamSet<'a, (Query<&'static Velocity>,)>,
+    }
+
+    fn two_sets(_: ParamSet<(Query<&mut Position>,)>, _: ParamSet<(Query<&Velocity>,)>) {}
+    fn nested_set(_: ParamSet<(Query<&mut Position>,)>, _: WithSet) {}
+
+    /// Verifies that systems with more than one `ParamSet` are rejected when they're added.
+    #[test]
+    fn rejects_two_sets() {
+        let mut world = World::default();
+        assert!(world.try_add_system(two_sets).is_err());
+        let conflict = world.try_add_system(nested_set).unwrap_err();
+        assert!(conflict.to_string().contains("more than one parameter"));
+    }
+}
@@ -0,0 +1,89 @@
+use {
+    crate::{
+        _crate_prelude::*,
+        system::{access::Access, order::SystemOrder, System},
+    },
+    alloc::{format, string::String},
+};
+
+/// Two systems chained together: the first system's output is given to the second system as its
+/// input. These are made with `IntoSystem::pipe`.
+///
+/// The second system returns the output for the whole pipe. Since a `PipeSystem` is a `System`,
+/// pipes can be chained as many times as needed.
+pub struct PipeSystem<First: System, Second: System<In = First::Out>> {
+    first: First,
+    second: Second,
+    name: String,
+}
+impl<First: System, Second: System<In = First::Out>> PipeSystem<First, Second> {
+    /// Chain two systems together.
+    pub fn new(first: First, second: Second) -> Self {
+        let name = format!("{} | {}", first.name(), second.name());
+        Self {
+            first,
+            second,
+            name,
+        }
+    }
+}
+impl<First: System, Second: System<In = First::Out>> System for PipeSystem<First, Second> {
+    type In = First::In;
+    type Out = Second::Out;
+
+    fn execute(&mut self, input: Self::In, world: &mut World) -> Self::Out {
+        let output = self.first.execute(input, world);
+        self.second.execute(output, world)
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    /// The piped systems run one after the other, so they can't conflict with each other.
+    fn access(&self) -> Access {
+        let mut access = self.first.access();
+        access.extend_unchecked(&self.second.access());
+        access
+    }
+    fn order(&self) -> SystemOrder {
+        let mut order = self.first.order();
+        order.extend(self.second.order());
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{self as secs, _crate_prelude::*, prelude::*};
+
+    #[derive(Component)]
+    struct Score(u32);
+
+    fn total_score(query: &Query<&Score>) -> u32 {
+        query.iter().map(|score| score.0).sum()
+    }
+
+    fn double(In(score): In<u32>) -> u32 {
+        score * 2
+    }
+
+    fn record(In(score): In<u32>, scores: &mut Resource<Vec<u32>>) {
+        scores.get_mut().push(score);
+    }
+
+    /// Verifies that outputs are piped between systems, and returned from `run_system_once`.
+    #[test]
+    fn pipe_outputs() {
+        let mut world = World::default();
+        world.insert_resource(Vec::<u32>::new());
+        world.spawn(Score(3));
+        world.spawn(Score(4));
+
+        assert_eq!(world.run_system_once(total_score), 7);
+        assert_eq!(world.run_system_once(total_score.pipe(double)), 14);
+
+        world.add_system(total_score.pipe(double).pipe(record));
+        world.run_once();
+
+        assert_eq!(*world.get_resource::<Vec<u32>>().unwrap(), [14]);
+    }
+}
@@ -1,15 +1,29 @@
 use {
-    crate::{_crate_prelude::*, entity::ComponentBorrow, system::WorldData},
-    alloc::rc::Rc,
-    core::{
-        cell::{Ref, RefCell, RefMut},
-        marker::PhantomData,
+    crate::{
+        _crate_prelude::*,
+        entity::ComponentBorrow,
+        sync::{self, Ref, RefMut},
+        system::{access::Access, WorldData},
+        world::storage::ComponentFetch,
     },
+    core::marker::PhantomData,
 };
 
+#[cfg(feature = "parallel")]
+pub mod par_iter;
+
 /// Queries give systems access to entities' components in the world.
+///
+/// Components are stored in columns, and borrowing a component from a query borrows its whole
+/// column (see `world::storage::table`). So, a query can't hand out two mutable borrows of the
+/// same component at once - collecting `iter()` from a `Query<&mut C>` into a `Vec` panics, for
+/// example. Iterating with a `for` loop is fine, since each entity's borrows are dropped before
+/// the next entity's components are borrowed.
 pub struct Query<B: Queryable> {
-    bundles: Vec<(usize, Vec<Rc<RefCell<dyn Component>>>)>,
+    /// Every entity that matched the query.
+    entities: Vec<usize>,
+    /// Where to find each of the queried components, for every entity in `entities`.
+    components: Vec<ComponentFetch>,
     _bundle_ty: PhantomData<B>,
 }
 impl<Q: Queryable> Query<Q> {
@@ -33,55 +47,32 @@ impl<Q: Queryable> Query<Q> {
 
     /// The total number of entities that satisfied this query.
     pub fn len(&self) -> usize {
-        self.bundles.len()
+        self.entities.len()
     }
     pub fn is_empty(&self) -> bool {
-        self.bundles.len() == 0
+        self.entities.is_empty()
     }
 }
 
 /// Allow `Query`s to be used as system parameters.
 impl<Q: Queryable + 'static> WorldData for Query<Q> {
-    fn take(world: &mut World) -> Self {
-        let types = Q::type_ids();
-        let num_types = types.len();
-        let mut archetypes = Vec::with_capacity(num_types);
-
-        for ty in types {
-            archetypes.push(
-                world
-                    .storage
-                    .get_archetype(ty)
-                    .expect("TODO: Error handling. Query failed to get archetype it needed."),
-            );
-        }
-
-        let mut bundles = Vec::new();
-        let mut idx = 0;
-        let max = world.storage.num_entities;
-
-        'bundles: while idx < max {
-            let mut cache = Vec::with_capacity(num_types);
-            for archetype in archetypes.iter() {
-                if let Some(component) = archetype.borrow_mut().get_component(idx) {
-                    cache.push(component)
-                } else {
-                    idx += 1;
-                    continue 'bundles;
-                }
-            }
-            bundles.push((idx, cache));
-
-            idx += 1;
-        }
+    type State = ();
 
+    /// See `Storage::query` for how the components are found.
+    fn take(world: &mut World, _: &mut Self::State) -> Self {
+        let (entities, components) = world.storage.query(&Q::type_ids());
         Self {
-            bundles,
+            entities,
+            components,
             _bundle_ty: PhantomData,
         }
     }
 
-    fn release(self, _world: &mut World) {}
+    fn release(self, _world: &mut World, _: &mut Self::State) {}
+
+    fn access(access: &mut Access) {
+        Q::access(access);
+    }
 }
 
 /// An iterator for `Query`s. Iterates over components from the query.
@@ -93,13 +84,14 @@ impl<'a, Q: Queryable> Iterator for QueryIter<'a, Q> {
     type Item = Q::QueryResult<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.idx == self.query.bundles.len() {
+        if self.idx == self.query.len() {
             return None;
         }
 
         self.idx += 1;
         Some(Q::from_components(
-            &mut self.query.bundles[self.idx - 1].1.iter(),
+            &mut self.query.components.iter(),
+            self.idx - 1,
         ))
     }
 }
@@ -113,13 +105,15 @@ impl<'a, Q: Queryable> Iterator for QueryEntityIter<'a, Q> {
     type Item = (usize, Q::QueryResult<'a>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.idx == self.query.bundles.len() {
+        if self.idx == self.query.len() {
             return None;
         }
 
-        let (entity, components) = &self.query.bundles[self.idx];
         self.idx += 1;
-        Some((entity.clone(), Q::from_components(&mut components.iter())))
+        Some((
+            self.query.entities[self.idx - 1],
+            Q::from_components(&mut self.query.components.iter(), self.idx - 1),
+        ))
     }
 }
 
@@ -148,11 +142,53 @@ pub trait Queryable {
     /// The `TypeId`s for the components this query needs.
     fn type_ids() -> Vec<TypeId>;
 
-    /// Build the query from the components it needs.
+    /// Records the components this query reads and writes.
+    fn access(access: &mut Access);
+
+    /// Borrows the components for the entity at `idx` in the query. `components` has one
+    /// `ComponentFetch` for each type in `type_ids`, in the same order.
     fn from_components<'a>(
-        components: &mut impl Iterator<Item = &'a Rc<RefCell<dyn Component>>>,
+        components: &mut impl Iterator<Item = &'a ComponentFetch>,
+        idx: usize,
     ) -> Self::QueryResult<'a>;
+
+    /// What `par_iter` gives each entity: plain references to its components.
+    #[cfg(feature = "parallel")]
+    type ParItem<'a>: Send;
+    /// Borrows of every column this query needs. See `par_iter`.
+    #[cfg(feature = "parallel")]
+    type Columns<'a>;
+
+    /// The components `par_iter` hands to a thread, for a range of the query's entities.
+    #[cfg(feature = "parallel")]
+    type ParBatch<'a>: Send;
+
+    /// Borrows every column this query's components are stored in.
+    #[cfg(feature = "parallel")]
+    fn borrow_columns<'a>(
+        components: &mut impl Iterator<Item = &'a ComponentFetch>,
+    ) -> Self::Columns<'a>;
+    /// Gets a batch with the components of every entity in the query, from the borrowed columns.
+    #[cfg(feature = "parallel")]
+    fn par_batch<'a>(
+        columns: &'a mut Self::Columns<'_>,
+        components: &mut impl Iterator<Item = &'a ComponentFetch>,
+    ) -> Self::ParBatch<'a>;
+    /// If `batch` can be split before its entity at `idx`. See `ParMut`.
+    #[cfg(feature = "parallel")]
+    fn can_split(batch: &Self::ParBatch<'_>, idx: usize) -> bool;
+    /// Splits `batch` into the entities before `idx`, and the rest. This is only called if
+    /// `can_split` returned `true`.
+    #[cfg(feature = "parallel")]
+    fn split_batch(
+        batch: Self::ParBatch<'_>,
+        idx: usize,
+    ) -> (Self::ParBatch<'_>, Self::ParBatch<'_>);
+    /// Gets the components of the entity at `idx` in `batch`.
+    #[cfg(feature = "parallel")]
+    fn batch_item<'a>(batch: &'a mut Self::ParBatch<'_>, idx: usize) -> Self::ParItem<'a>;
 }
+#[allow(clippy::needless_lifetimes)]
 impl<'b, A: Component> Queryable for &'b A {
     type QueryResult<'a> = Ref<'a, A>;
 
@@ -160,14 +196,64 @@ impl<'b, A: Component> Queryable for &'b A {
         vec![TypeId::of::<A>()]
     }
 
+    fn access(access: &mut Access) {
+        access.read_component::<A>();
+    }
+
     fn from_components<'a>(
-        components: &mut impl Iterator<Item = &'a Rc<RefCell<dyn Component>>>,
+        components: &mut impl Iterator<Item = &'a ComponentFetch>,
+        idx: usize,
     ) -> Self::QueryResult<'a> {
-        Ref::map(components.next().unwrap().borrow(), |component| {
-            component.as_any_ref().downcast_ref().unwrap()
-        })
+        let fetch = components.next().unwrap();
+        let (column, row) = fetch.rows[idx];
+        sync::downcast_index(&fetch.columns[column], row)
+    }
+
+    #[cfg(feature = "parallel")]
+    type ParItem<'a> = &'a A;
+    #[cfg(feature = "parallel")]
+    type Columns<'a> = Vec<Ref<'a, Vec<A>>>;
+
+    #[cfg(feature = "parallel")]
+    fn borrow_columns<'a>(
+        components: &mut impl Iterator<Item = &'a ComponentFetch>,
+    ) -> Self::Columns<'a> {
+        let fetch = components.next().unwrap();
+        fetch
+            .columns
+            .iter()
+            .map(|column| sync::downcast_ref(column))
+            .collect()
+    }
+    #[cfg(feature = "parallel")]
+    type ParBatch<'a> = (&'a [Ref<'a, Vec<A>>], &'a [(usize, usize)]);
+
+    #[cfg(feature = "parallel")]
+    fn par_batch<'a>(
+        columns: &'a mut Self::Columns<'_>,
+        components: &mut impl Iterator<Item = &'a ComponentFetch>,
+    ) -> Self::ParBatch<'a> {
+        (columns, &components.next().unwrap().rows)
+    }
+    #[cfg(feature = "parallel")]
+    fn can_split(_: &Self::ParBatch<'_>, _: usize) -> bool {
+        true
+    }
+    #[cfg(feature = "parallel")]
+    fn split_batch(
+        batch: Self::ParBatch<'_>,
+        idx: usize,
+    ) -> (Self::ParBatch<'_>, Self::ParBatch<'_>) {
+        let (before, after) = batch.1.split_at(idx);
+        ((batch.0, before), (batch.0, after))
+    }
+    #[cfg(feature = "parallel")]
+    fn batch_item<'a>(batch: &'a mut Self::ParBatch<'_>, idx: usize) -> Self::ParItem<'a> {
+        let (column, row) = batch.1[idx];
+        &batch.0[column][row]
     }
 }
+#[allow(clippy::needless_lifetimes)]
 impl<'b, A: Component> Queryable for &'b mut A {
     type QueryResult<'a> = RefMut<'a, A>;
 
@@ -175,14 +261,123 @@ impl<'b, A: Component> Queryable for &'b mut A {
         vec![TypeId::of::<A>()]
     }
 
+    fn access(access: &mut Access) {
+        access.write_component::<A>();
+    }
+
     fn from_components<'a>(
-        components: &mut impl Iterator<Item = &'a Rc<RefCell<dyn Component>>>,
+        components: &mut impl Iterator<Item = &'a ComponentFetch>,
+        idx: usize,
     ) -> Self::QueryResult<'a> {
-        RefMut::map(components.next().unwrap().borrow_mut(), |component| {
-            component.as_any_mut().downcast_mut().unwrap()
-        })
+        let fetch = components.next().unwrap();
+        let (column, row) = fetch.rows[idx];
+        sync::downcast_index_mut(&fetch.columns[column], row)
+    }
+
+    #[cfg(feature = "parallel")]
+    type ParItem<'a> = &'a mut A;
+    #[cfg(feature = "parallel")]
+    type Columns<'a> = Vec<RefMut<'a, Vec<A>>>;
+
+    #[cfg(feature = "parallel")]
+    fn borrow_columns<'a>(
+        components: &mut impl Iterator<Item = &'a ComponentFetch>,
+    ) -> Self::Columns<'a> {
+        let fetch = components.next().unwrap();
+        fetch
+            .columns
+            .iter()
+            .map(|column| sync::downcast_mut(column))
+            .collect()
+    }
+    #[cfg(feature = "parallel")]
+    type ParBatch<'a> = ParMut<'a, A>;
+
+    #[cfg(feature = "parallel")]
+    fn par_batch<'a>(
+        columns: &'a mut Self::Columns<'_>,
+        components: &mut impl Iterator<Item = &'a ComponentFetch>,
+    ) -> Self::ParBatch<'a> {
+        let rows = &components.next().unwrap().rows;
+        ParMut {
+            rows,
+            columns: columns
+                .iter_mut()
+                .enumerate()
+                .map(|(idx, column)| (idx, 0, column.as_mut_slice()))
+                .collect(),
+            sorted: rows.windows(2).all(|rows| rows[0] < rows[1]),
+        }
+    }
+    #[cfg(feature = "parallel")]
+    fn can_split(batch: &Self::ParBatch<'_>, idx: usize) -> bool {
+        let (before, after) = batch.rows.split_at(idx);
+        batch.sorted || before.iter().max() < after.iter().min()
+    }
+    #[cfg(feature = "parallel")]
+    fn split_batch(
+        batch: Self::ParBatch<'_>,
+        idx: usize,
+    ) -> (Self::ParBatch<'_>, Self::ParBatch<'_>) {
+        let (before, after) = batch.rows.split_at(idx);
+        // Every row before `idx` comes before this one, and every row after it comes after
+        let (split_column, split_row) = match batch.sorted {
+            true => after[0],
+            false => *after.iter().min().unwrap(),
+        };
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        for (column, start, slice) in batch.columns {
+            if column < split_column {
+                left.push((column, start, slice));
+            } else if column > split_column {
+                right.push((column, start, slice));
+            } else {
+                let (head, tail) = slice.split_at_mut(split_row - start);
+                left.push((column, start, head));
+                right.push((column, split_row, tail));
+            }
+        }
+        let left = ParMut {
+            rows: before,
+            columns: left,
+            sorted: batch.sorted,
+        };
+        let right = ParMut {
+            rows: after,
+            columns: right,
+            sorted: batch.sorted,
+        };
+        (left, right)
+    }
+    #[cfg(feature = "parallel")]
+    fn batch_item<'a>(batch: &'a mut Self::ParBatch<'_>, idx: usize) -> Self::ParItem<'a> {
+        let (column, row) = batch.rows[idx];
+        let found = batch
+            .columns
+            .binary_search_by_key(&column, |(column, ..)| *column)
+            .unwrap();
+        let (_, start, slice) = &mut batch.columns[found];
+        &mut slice[row - *start]
     }
 }
+
+/// The components a thread gets from `par_iter` for a mutable component: the part of each column
+/// that holds the batch's rows.
+///
+/// A batch can only be split where every row before the split comes before every row after it,
+/// so that each half gets its own part of the columns. Queries over tables always visit rows in
+/// order, so they can be split anywhere. Queries with sparse sets might not, and if a batch
+/// can't be split, one thread handles the whole thing.
+#[cfg(feature = "parallel")]
+pub struct ParMut<'a, A> {
+    /// The column and row of each entity's component.
+    rows: &'a [(usize, usize)],
+    /// The index of each column the batch uses, the row its slice starts at, and the slice.
+    columns: Vec<(usize, usize, &'a mut [A])>,
+    /// If `rows` are in order.
+    sorted: bool,
+}
 macro_rules! queryable_impl {
     ($_ty:ident) => {
 
@@ -195,10 +390,61 @@ macro_rules! queryable_impl {
                 vec![$(TypeId::of::<$ty::Component>(),)*]
             }
 
+            fn access(access: &mut Access) {
+                $($ty::access(access);)*
+            }
+
             fn from_components<'a>(
-                components: &mut impl Iterator<Item = &'a Rc<RefCell<dyn Component>>>,
+                components: &mut impl Iterator<Item = &'a ComponentFetch>,
+                idx: usize,
             ) -> Self::QueryResult<'a> {
-                ($($ty::from_components(components),)*)
+                ($($ty::from_components(components, idx),)*)
+            }
+
+            #[cfg(feature = "parallel")]
+            type ParItem<'a> = ($($ty::ParItem<'a>,)*);
+            #[cfg(feature = "parallel")]
+            type Columns<'a> = ($($ty::Columns<'a>,)*);
+            #[cfg(feature = "parallel")]
+            type ParBatch<'a> = ($($ty::ParBatch<'a>,)*);
+
+            #[cfg(feature = "parallel")]
+            fn borrow_columns<'a>(
+                components: &mut impl Iterator<Item = &'a ComponentFetch>,
+            ) -> Self::Columns<'a> {
+                ($($ty::borrow_columns(components),)*)
+            }
+            // Each variable in these is named after its type parameter
+            #[cfg(feature = "parallel")]
+            #[allow(non_snake_case)]
+            fn par_batch<'a>(
+                columns: &'a mut Self::Columns<'_>,
+                components: &mut impl Iterator<Item = &'a ComponentFetch>,
+            ) -> Self::ParBatch<'a> {
+                let ($($ty,)*) = columns;
+                ($($ty::par_batch($ty, components),)*)
+            }
+            #[cfg(feature = "parallel")]
+            #[allow(non_snake_case)]
+            fn can_split(batch: &Self::ParBatch<'_>, idx: usize) -> bool {
+                let ($($ty,)*) = batch;
+                $($ty::can_split($ty, idx) &&)* true
+            }
+            #[cfg(feature = "parallel")]
+            #[allow(non_snake_case)]
+            fn split_batch(
+                batch: Self::ParBatch<'_>,
+                idx: usize,
+            ) -> (Self::ParBatch<'_>, Self::ParBatch<'_>) {
+                let ($($ty,)*) = batch;
+                $(let $ty = $ty::split_batch($ty, idx);)*
+                (($($ty.0,)*), ($($ty.1,)*))
+            }
+            #[cfg(feature = "parallel")]
+            #[allow(non_snake_case)]
+            fn batch_item<'a>(batch: &'a mut Self::ParBatch<'_>, idx: usize) -> Self::ParItem<'a> {
+                let ($($ty,)*) = batch;
+                ($($ty::batch_item($ty, idx),)*)
             }
         }
 
@@ -226,6 +472,7 @@ mod tests {
         accepts_queryable(&MyComponent {});
         accepts_queryable(&mut MyComponent {});
         accepts_queryable((&mut MyComponent {}, &MyComponent {}));
+        accepts_queryable((&MyComponent {}, &mut OtherComponent {}));
 
         // Verify this also works for systems.
         fn _system(_: &Query<&MyComponent>) {}
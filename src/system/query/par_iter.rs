@@ -0,0 +1,172 @@
+//! Iterating over a `Query` on multiple threads. This module is only available with the
+//! `parallel` feature.
+//!
+//! `Query::par_iter` and `Query::par_iter_mut` split the query's entities into batches, and hand
+//! the batches out to threads as they finish their previous batch. This is only worth it for
+//! systems that do a lot of work for each entity - for cheap systems, spawning the threads takes
+//! longer than just iterating.
+//!
+//! Unlike the system executor (see the `executor` module), the threads are spawned with
+//! `std::thread::scope` every time the query is iterated. The closure given to `for_each` and the
+//! components it's given are borrowed from the system, so they can't be sent to the executor's
+//! `ThreadPool` without unsafe code.
+//!
+//! Components are locked by column (see `world::storage::table`), so threads can't each borrow
+//! the components they need. Instead, every column the query needs is borrowed once, on the
+//! current thread, and split into batches (see `Queryable::ParBatch`). Each batch holds a range
+//! of the query's entities and the parts of the columns their components are in, and gives out
+//! plain references (`&C` and `&mut C`) to them. That's why `par_iter` gives references instead
+//! of `Ref`s and `RefMut`s. Batches are split off as threads ask for them, so the components
+//! are never collected up front.
+
+use {
+    super::{Query, Queryable},
+    std::{sync::Mutex, thread},
+};
+
+impl<Q: Queryable> Query<Q> {
+    /// Iterates over the queried components on multiple threads. See the module docs.
+    pub fn par_iter(&self) -> QueryParIter<'_, Q> {
+        QueryParIter {
+            query: self,
+            batch_size: None,
+        }
+    }
+    /// Iterates over the queried components on multiple threads. This is the same as `par_iter`,
+    /// but makes it clear that the components are being modified.
+    pub fn par_iter_mut(&mut self) -> QueryParIter<'_, Q> {
+        self.par_iter()
+    }
+}
+
+/// A parallel iterator for `Query`s. Made with `Query::par_iter` or `Query::par_iter_mut`.
+pub struct QueryParIter<'a, Q: Queryable> {
+    query: &'a Query<Q>,
+    batch_size: Option<usize>,
+}
+impl<'a, Q: Queryable> QueryParIter<'a, Q> {
+    /// Sets how many entities each thread handles at a time. By default, the entities are split
+    /// evenly between every available thread.
+    ///
+    /// Panics if `batch_size` is 0.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "A query's batch size can't be 0");
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    /// Calls `f` with the components of every entity in the query, on multiple threads.
+    pub fn for_each(self, f: impl Fn(Q::ParItem<'_>) + Sync) {
+        self.for_each_with_entity(|_, components| f(components));
+    }
+
+    /// Calls `f` with every entity in the query and its components, on multiple threads. See
+    /// `Query::iter_with_entity`.
+    pub fn for_each_with_entity(self, f: impl Fn(usize, Q::ParItem<'_>) + Sync) {
+        let query = self.query;
+        let mut columns = Q::borrow_columns(&mut query.components.iter());
+        let batch = Q::par_batch(&mut columns, &mut query.components.iter());
+
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        let batch_size = self
+            .batch_size
+            .unwrap_or_else(|| query.len().div_ceil(threads).max(1));
+        // The entities that haven't been handed out to a thread yet
+        let rest = Mutex::new(Some((query.entities.as_slice(), batch)));
+
+        let run = || loop {
+            let mut rest = rest.lock().unwrap();
+            let Some((mut entities, mut batch)) = rest.take() else {
+                break;
+            };
+            if entities.len() > batch_size && Q::can_split(&batch, batch_size) {
+                let (before, after) = Q::split_batch(batch, batch_size);
+                *rest = Some((&entities[batch_size..], after));
+                (entities, batch) = (&entities[..batch_size], before);
+            }
+            drop(rest);
+
+            for (idx, entity) in entities.iter().enumerate() {
+                f(*entity, Q::batch_item(&mut batch, idx));
+            }
+        };
+
+        thread::scope(|scope| {
+            for _ in 1..threads.min(query.len().div_ceil(batch_size)) {
+                scope.spawn(run);
+            }
+            // The current thread handles batches too, instead of just waiting
+            run();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{self as secs, _crate_prelude::*, prelude::*};
+
+    #[derive(Component)]
+    struct Value(u64);
+    #[derive(Component)]
+    struct Multiplier(u64);
+
+    fn multiply(query: &mut Query<(&mut Value, &Multiplier)>) {
+        query
+            .par_iter_mut()
+            .batch_size(7)
+            .for_each(|(value, multiplier)| value.0 *= multiplier.0);
+    }
+
+    /// Verifies that every entity in the query is visited exactly once.
+    #[test]
+    fn par_iter() {
+        let mut world = World::default();
+        for idx in 0..100 {
+            world.spawn((Value(idx), Multiplier(3)));
+        }
+        world.add_system(multiply);
+        world.run_once();
+
+        let values: Vec<u64> = world
+            .run_system_once(|query: &Query<&Value>| query.iter().map(|value| value.0).collect());
+        assert_eq!(values, (0..100).map(|idx| idx * 3).collect::<Vec<_>>());
+    }
+
+    #[derive(Component)]
+    struct Extra;
+    #[derive(Component)]
+    #[component(storage = "SparseSet")]
+    struct Selected;
+
+    fn double_selected(query: &mut Query<(&Selected, &mut Value)>) {
+        query
+            .par_iter_mut()
+            .batch_size(3)
+            .for_each(|(_, value)| value.0 *= 2);
+    }
+
+    /// Verifies that batches are split across tables, and that queries whose rows aren't in
+    /// order still visit every entity once.
+    #[test]
+    fn split_batches() {
+        let mut world = World::default();
+        let entities: Vec<usize> = (0..20)
+            .map(|idx| match idx % 2 {
+                0 => world.spawn((Value(idx), Multiplier(3))),
+                _ => world.spawn((Value(idx), Multiplier(3), Extra)),
+            })
+            .collect();
+        // Selecting entities backwards means the sparse set's order doesn't match the tables'
+        for entity in entities.iter().rev() {
+            world.insert_components(*entity, Selected);
+        }
+        world.add_system(multiply);
+        world.add_system(double_selected);
+        world.run_once();
+
+        let mut values: Vec<u64> = world
+            .run_system_once(|query: &Query<&Value>| query.iter().map(|value| value.0).collect());
+        values.sort_unstable();
+        assert_eq!(values, (0..20).map(|idx| idx * 6).collect::<Vec<_>>());
+    }
+}
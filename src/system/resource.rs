@@ -1,20 +1,22 @@
 use {
-    crate::{_crate_prelude::*, system::WorldData},
-    alloc::rc::Rc,
-    core::{
-        cell::{Ref, RefCell, RefMut},
-        marker::PhantomData,
+    crate::{
+        _crate_prelude::*,
+        sync::{self, AnyData, Lock, Ref, RefMut, Shared},
+        system::{access::Access, WorldData},
     },
+    core::marker::PhantomData,
 };
 
 /// A resource stored in the World.
 pub struct Resource<R: 'static> {
-    value: Rc<RefCell<dyn Any>>,
+    value: Shared<Lock<AnyData>>,
     r: PhantomData<R>,
 }
 /// Allow `Resource`s to be used as system parameters.
 impl<R: 'static> WorldData for Resource<R> {
-    fn take(world: &mut World) -> Self {
+    type State = ();
+
+    fn take(world: &mut World, _: &mut Self::State) -> Self {
         Self {
             value: world
                 .storage
@@ -24,20 +26,24 @@ impl<R: 'static> WorldData for Resource<R> {
         }
     }
 
-    fn release(self, _world: &mut World) {}
+    fn release(self, _world: &mut World, _: &mut Self::State) {}
+
+    fn access(access: &mut Access) {
+        access.read_resource::<R>();
+    }
+    fn access_mut(access: &mut Access) {
+        access.write_resource::<R>();
+    }
 }
 impl<R: 'static> Resource<R> {
     /// Immutably get a resource's value.
     pub fn get(&self) -> Ref<'_, R> {
-        Ref::map(self.value.borrow(), |resource| {
-            resource.downcast_ref().unwrap()
-        })
+        sync::downcast_ref(&self.value)
     }
 
-    /// Mutably get a resource's value.
-    pub fn get_mut(&self) -> RefMut<'_, R> {
-        RefMut::map(self.value.borrow_mut(), |resource| {
-            resource.downcast_mut().unwrap()
-        })
+    /// Mutably get a resource's value. The `Resource` has to be borrowed mutably (ie,
+    /// `&mut Resource<R>`) to do this.
+    pub fn get_mut(&mut self) -> RefMut<'_, R> {
+        sync::downcast_mut(&self.value)
     }
 }
@@ -0,0 +1,98 @@
+//! Stepping through the World's systems one at a time, for debugging.
+//!
+//! Once `World::enable_stepping` is called, `World::step` runs exactly one system and then
+//! pauses, so a debugger can inspect the World between systems. `World::next_system` tells you
+//! which system will run next.
+//!
+//! `World::run_once` still works while stepping - it runs systems from wherever the World is paused
+//! until the end of the frame. If a system has a breakpoint (see `World::add_breakpoint`),
+//! `run_once` pauses right before that system instead, and the next `step` or `run_once` starts by
+//! running it.
+
+use {
+    crate::{_crate_prelude::*, system::order::name_matches},
+    alloc::string::{String, ToString},
+};
+
+/// Where the World's `Systems` are paused, and which systems they should pause at.
+#[derive(Clone, Debug, Default)]
+pub struct Stepping {
+    /// The index of the next system to run. When this is 0, the next step starts a new frame.
+    pub cursor: usize,
+    /// The names of systems to pause before. See `Stepping::is_breakpoint`.
+    pub breakpoints: Vec<String>,
+}
+impl Stepping {
+    /// Adds a breakpoint on the system named `name`.
+    pub fn add_breakpoint(&mut self, name: &str) {
+        if !self.breakpoints.iter().any(|breakpoint| breakpoint == name) {
+            self.breakpoints.push(name.to_string());
+        }
+    }
+    /// Removes the breakpoint on the system named `name`, if there is one.
+    pub fn remove_breakpoint(&mut self, name: &str) {
+        self.breakpoints.retain(|breakpoint| breakpoint != name);
+    }
+
+    /// If there's a breakpoint on the system named `system`. A breakpoint can use the system's
+    /// full name or just the last part of it; see `order::name_matches`.
+    pub fn is_breakpoint(&self, system: &str) -> bool {
+        self.breakpoints
+            .iter()
+            .any(|breakpoint| name_matches(system, breakpoint))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{_crate_prelude::*, prelude::*};
+
+    fn first(log: &mut Resource<Vec<&'static str>>) {
+        log.get_mut().push("first");
+    }
+    fn second(log: &mut Resource<Vec<&'static str>>) {
+        log.get_mut().push("second");
+    }
+    fn third(log: &mut Resource<Vec<&'static str>>) {
+        log.get_mut().push("third");
+    }
+
+    fn log(world: &World) -> Vec<&'static str> {
+        world.get_resource::<Vec<&'static str>>().unwrap().clone()
+    }
+
+    /// Verifies that `step` runs one system at a time, and `run_once` pauses at breakpoints.
+    #[test]
+    fn stepping() {
+        let mut world = World::default();
+        world.insert_resource(Vec::<&'static str>::new());
+        world.add_system(first);
+        world.add_system(second);
+        world.add_system(third);
+        world.enable_stepping();
+
+        assert!(world.next_system().unwrap().ends_with("first"));
+        world.step();
+        assert_eq!(log(&world), ["first"]);
+        assert!(world.next_system().unwrap().ends_with("second"));
+
+        // Finishes the frame
+        world.run_once();
+        assert_eq!(log(&world), ["first", "second", "third"]);
+        assert!(world.next_system().unwrap().ends_with("first"));
+
+        world.add_breakpoint("third");
+        world.run_once();
+        assert_eq!(log(&world).len(), 5);
+        assert!(world.next_system().unwrap().ends_with("third"));
+
+        // Resuming runs the system at the breakpoint
+        world.run_once();
+        assert_eq!(log(&world).len(), 6);
+
+        world.disable_stepping();
+        assert!(world.next_system().is_none());
+        world.run_once();
+        assert_eq!(log(&world).len(), 9);
+    }
+}
@@ -0,0 +1,180 @@
+//! Tracks time in the `World`, and runs systems on a fixed timestep.
+//!
+//! SECS is `no_std`, so it can't read the time by itself. Instead, the World gets the time from a
+//! `Clock`, which is set with `World::set_clock`. Games can implement `Clock` with
+//! `std::time::Instant` (or whatever their platform provides), and tests can use a fake clock
+//! that they advance manually.
+//!
+//! Once a clock is set, the World keeps the `Time` resource up to date. Systems added with
+//! `World::add_fixed_system` run a whole number of times each frame - once for every
+//! `Time::fixed_step` that's passed since the last time they ran. They always run before the
+//! World's other systems.
+
+use {
+    crate::sync::{MaybeSend, MaybeSync},
+    core::time::Duration,
+};
+
+/// A source of time for the World. With the `parallel` feature, clocks have to be `Send + Sync`,
+/// since systems running on other threads are timed with them.
+pub trait Clock: MaybeSend + MaybeSync {
+    /// The current time. This can be measured from any starting point, as long as it never goes
+    /// backwards.
+    fn now(&self) -> Duration;
+}
+
+/// A resource storing the World's time. This is updated every time the World's systems run, as
+/// long as the World has a `Clock`.
+#[derive(Clone, Copy, Debug)]
+pub struct Time {
+    /// The time between this frame and the last frame.
+    pub delta: Duration,
+    /// The total time that's passed since the clock was set.
+    pub elapsed: Duration,
+    /// How often fixed systems run. Defaults to 60 times per second.
+    pub fixed_step: Duration,
+    /// Time that's passed, but hasn't been used up by a fixed step yet. This is always less
+    /// than `fixed_step` once the fixed systems have run.
+    pub accumulated: Duration,
+    /// The most times the fixed systems can run in one frame. If more time than that has
+    /// passed, the rest is dropped; otherwise a frame that's slow because of its fixed steps
+    /// would have even more steps to run next frame, and the game would never catch up.
+    /// Defaults to 8.
+    pub max_fixed_steps: u32,
+    /// How many times the fixed systems ran this frame.
+    pub fixed_steps: u32,
+    /// What the clock said the last time this was updated.
+    pub last_update: Duration,
+}
+impl Time {
+    /// Makes a new `Time`, starting at the given clock time.
+    pub fn new(now: Duration) -> Self {
+        Self {
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            fixed_step: Duration::from_secs(1) / 60,
+            accumulated: Duration::ZERO,
+            max_fixed_steps: 8,
+            fixed_steps: 0,
+            last_update: now,
+        }
+    }
+
+    /// Advances time to `now`, and returns how many fixed steps should run.
+    pub fn update(&mut self, now: Duration) -> u32 {
+        self.delta = now.saturating_sub(self.last_update);
+        self.elapsed += self.delta;
+        self.last_update = now;
+        self.accumulated += self.delta;
+
+        self.fixed_steps = 0;
+        if !self.fixed_step.is_zero() {
+            while self.accumulated >= self.fixed_step && self.fixed_steps < self.max_fixed_steps {
+                self.accumulated -= self.fixed_step;
+                self.fixed_steps += 1;
+            }
+            if self.accumulated >= self.fixed_step {
+                let leftover = self.accumulated.as_nanos() % self.fixed_step.as_nanos();
+                self.accumulated = Duration::from_nanos(leftover as u64);
+            }
+        }
+        self.fixed_steps
+    }
+
+    /// How far time is between the last fixed step and the next one, from 0 to 1. This is
+    /// useful for interpolating between fixed steps while rendering.
+    pub fn overstep_fraction(&self) -> f32 {
+        if self.fixed_step.is_zero() {
+            0.0
+        } else {
+            self.accumulated.as_secs_f32() / self.fixed_step.as_secs_f32()
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*,
+        crate::{_crate_prelude::*, prelude::*},
+        alloc::sync::Arc,
+        core::sync::atomic::{AtomicU64, Ordering},
+    };
+
+    /// A clock that only moves when the test advances it. It counts milliseconds. Clones share
+    /// the same time, so tests can keep one to advance the clock they gave the World.
+    #[derive(Clone, Default)]
+    pub(crate) struct FakeClock(Arc<AtomicU64>);
+    impl FakeClock {
+        pub(crate) fn advance(&self, millis: u64) {
+            self.0.fetch_add(millis, Ordering::Relaxed);
+        }
+    }
+    impl Clock for FakeClock {
+        fn now(&self) -> Duration {
+            Duration::from_millis(self.0.load(Ordering::Relaxed))
+        }
+    }
+
+    fn count_steps(steps: &mut Resource<u32>) {
+        *steps.get_mut() += 1;
+    }
+
+    /// Verifies that fixed systems run once for every fixed step that's passed, and carry
+    /// leftover time over to the next frame.
+    #[test]
+    fn fixed_steps() {
+        let clock = FakeClock::default();
+        let mut world = World::default();
+        world.set_clock(clock.clone());
+        world.get_resource_mut::<Time>().unwrap().fixed_step = Duration::from_millis(10);
+        world.insert_resource(0_u32);
+        world.add_fixed_system(count_steps);
+
+        clock.advance(25);
+        world.run_once();
+        assert_eq!(*world.get_resource::<u32>().unwrap(), 2);
+        let time = *world.get_resource::<Time>().unwrap();
+        assert_eq!(time.delta, Duration::from_millis(25));
+        assert_eq!(time.accumulated, Duration::from_millis(5));
+        assert_eq!(time.overstep_fraction(), 0.5);
+
+        clock.advance(4);
+        world.run_once();
+        assert_eq!(*world.get_resource::<u32>().unwrap(), 2);
+
+        clock.advance(1);
+        world.run_once();
+        assert_eq!(*world.get_resource::<u32>().unwrap(), 3);
+        assert_eq!(
+            world.get_resource::<Time>().unwrap().elapsed,
+            Duration::from_millis(30)
+        );
+    }
+
+    /// Verifies that a long frame only runs `max_fixed_steps` fixed steps, and drops the rest of
+    /// its time.
+    #[test]
+    fn max_fixed_steps() {
+        let clock = FakeClock::default();
+        let mut world = World::default();
+        world.set_clock(clock.clone());
+        let mut time = world.get_resource_mut::<Time>().unwrap();
+        time.fixed_step = Duration::from_millis(10);
+        time.max_fixed_steps = 3;
+        drop(time);
+        world.insert_resource(0_u32);
+        world.add_fixed_system(count_steps);
+
+        clock.advance(10_005);
+        world.run_once();
+        assert_eq!(*world.get_resource::<u32>().unwrap(), 3);
+        let time = *world.get_resource::<Time>().unwrap();
+        assert_eq!(time.fixed_steps, 3);
+        assert_eq!(time.accumulated, Duration::from_millis(5));
+
+        clock.advance(5);
+        world.run_once();
+        assert_eq!(*world.get_resource::<u32>().unwrap(), 4);
+    }
+}
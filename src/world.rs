@@ -1,13 +1,27 @@
 pub mod storage;
 pub use storage::*;
 
+#[cfg(feature = "std")]
+use crate::system::panic;
 use {
     crate::{
         _crate_prelude::*,
         entity::Bundle,
-        system::{command::Command, IntoSystem, System, Systems},
+        state::{self, States, TransitionSystems},
+        sync::{self, AnyData, Lock, MaybeSend, MaybeSync, Ref, RefMut, Shared},
+        system::{
+            access::AccessConflict,
+            command::{Command, CommandBuffer, CommandMode},
+            diagnostics::SystemHook,
+            dot,
+            error::{self, ErrorHandler, HandledSystem, SystemOutput},
+            order::Ambiguity,
+            stepping::Stepping,
+            IntoSystem, System, SystemId, Systems,
+        },
+        time::{Clock, Time},
     },
-    alloc::rc::Rc,
+    alloc::{rc::Rc, string::String},
     core::cell::RefCell,
 };
 
@@ -20,6 +34,31 @@ pub struct World {
     /// A flag for the run loop started in `World::run()`. When true, the loop breaks. The
     /// `ExitRunLoop` command sets this to true.
     pub exit_run_loop: bool,
+    /// The value given to the `ExitRunLoopWith` command, which `World::run()` returns.
+    pub exit_value: Option<Box<AnyData>>,
+    /// Handles errors returned by systems. By default, this panics. See
+    /// `World::set_error_handler`.
+    pub error_handler: ErrorHandler,
+    /// One-shot systems registered with `World::register_system`. A system's `SystemId` is its
+    /// index in this list. Systems are moved out of the list while they run.
+    pub registered_systems: Vec<Option<Box<dyn System<In = (), Out = ()>>>>,
+    /// The command buffer of the `Systems` that are currently running, if any. Commands from
+    /// systems are applied or deferred according to this. See `CommandBuffer`.
+    pub active_commands: Option<Shared<Lock<CommandBuffer>>>,
+    /// Systems that run on a fixed timestep. See the `time` module.
+    pub fixed_systems: Rc<RefCell<Systems>>,
+    /// Systems that were added while the `Systems` they're for were running (ie, by an
+    /// exclusive system). They can't be added until those `Systems` finish, so they're added
+    /// after `World::run_once` or `World::step`.
+    pub queued_systems: Vec<Box<dyn FnOnce()>>,
+    /// Where the World gets the current time from. See `World::set_clock`.
+    pub clock: Option<Shared<dyn Clock>>,
+    /// Hooks that are called before and after every system runs. See `World::add_system_hook`.
+    pub system_hooks: Vec<Box<dyn SystemHook>>,
+    /// If systems that panic are disabled, instead of the panic crashing the program. See
+    /// `World::set_isolate_panics`.
+    #[cfg(feature = "std")]
+    pub isolate_panics: bool,
 }
 impl Default for World {
     fn default() -> Self {
@@ -27,6 +66,16 @@ impl Default for World {
             storage: Storage::default(),
             systems: Rc::new(RefCell::new(Systems::default())),
             exit_run_loop: false,
+            exit_value: None,
+            error_handler: error::panic,
+            registered_systems: Vec::new(),
+            active_commands: None,
+            fixed_systems: Rc::new(RefCell::new(Systems::default())),
+            queued_systems: Vec::new(),
+            clock: None,
+            system_hooks: Vec::new(),
+            #[cfg(feature = "std")]
+            isolate_panics: false,
         }
     }
 }
@@ -34,25 +83,12 @@ impl World {
     /// Spawns an entity into the World. Returns its ID.
     #[inline]
     pub fn spawn(&mut self, components: impl Bundle) -> usize {
-        let entity = self.storage.spawn();
-        components.components().into_iter().for_each(|component| {
-            component.prep_storage(&mut self.storage);
-            self.storage.insert_component_boxed(entity, component);
-        });
-        entity
+        self.storage.spawn_bundle(components)
     }
     /// Spawns an entity, whose components are boxed, into the World. Returns its ID.
     #[inline]
     pub fn spawn_boxed(&mut self, components: Box<dyn Bundle>) -> usize {
-        let entity = self.storage.spawn();
-        components
-            .components_from_box()
-            .into_iter()
-            .for_each(|component| {
-                component.prep_storage(&mut self.storage);
-                self.storage.insert_component_boxed(entity, component);
-            });
-        entity
+        self.storage.spawn_with(components.components_from_box())
     }
     /// Spawns an entity into the World with no components. Returns the entity's ID.
     #[inline]
@@ -63,61 +99,413 @@ impl World {
     /// Registers a resource in the world. This will overwrite any existing resources
     /// of the same type.
     #[inline]
-    pub fn insert_resource(&mut self, resource: impl Any + 'static) {
+    pub fn insert_resource(&mut self, resource: impl Any + MaybeSend + MaybeSync) {
         self.storage.insert_resource(resource);
     }
-    /// Insert one or more components into an entity.
+    /// Immutably borrow a resource from the world, if it exists.
+    #[inline]
+    pub fn get_resource<R: 'static>(&self) -> Option<Ref<'_, R>> {
+        self.storage
+            .resources
+            .get(&TypeId::of::<R>())
+            .map(|resource| sync::downcast_ref(resource))
+    }
+    /// Mutably borrow a resource from the world, if it exists.
+    #[inline]
+    pub fn get_resource_mut<R: 'static>(&self) -> Option<RefMut<'_, R>> {
+        self.storage
+            .resources
+            .get(&TypeId::of::<R>())
+            .map(|resource| sync::downcast_mut(resource))
+    }
+    /// Insert one or more components into an entity. This moves the entity to the table for its
+    /// new set of components; see the `storage` module.
     #[inline]
     pub fn insert_components(&mut self, entity: usize, components: impl Bundle) {
-        components.components().into_iter().for_each(|component| {
-            component.prep_storage(&mut self.storage);
-            self.storage.insert_component_boxed(entity, component);
-        });
+        self.storage.insert_bundle(entity, components);
     }
     /// Remove a component from an entity.
     #[inline]
     pub fn remove_component<C: Component>(&mut self, entity: usize) {
-        self.storage
-            .get_archetype(TypeId::of::<C>())
-            .unwrap()
-            .borrow_mut()
-            .despawn(entity);
+        self.storage.remove_component(entity, TypeId::of::<C>());
     }
     /// Remove a component from an entity, by the component's `TypeId`.
     #[inline]
     pub fn remove_component_by_id(&mut self, entity: usize, component: TypeId) {
-        self.storage
-            .get_archetype(component)
-            .unwrap()
-            .borrow_mut()
-            .despawn(entity);
+        self.storage.remove_component(entity, component);
     }
 
-    /// Register a System in the World.
+    /// Register a System in the World. The system may return `()`, or a `Result<(), E>` -
+    /// errors it returns are sent to the World's error handler.
+    ///
+    /// If this is called by an exclusive system, the new system is added once the World's
+    /// systems finish running, so it runs from the next frame.
+    ///
+    /// Panics if the system accesses the same data in conflicting ways (see
+    /// `World::try_add_system`).
     #[inline]
-    pub fn add_system<S: System + 'static>(&mut self, system: impl IntoSystem<S>) {
-        self.systems.borrow_mut().push(system);
+    pub fn add_system<S: System<In = ()> + MaybeSend + MaybeSync + 'static>(
+        &mut self,
+        system: impl IntoSystem<S>,
+    ) where
+        S::Out: SystemOutput,
+    {
+        if let Err(conflict) = self.try_add_system(system) {
+            panic!("{conflict}");
+        }
+    }
+    /// Register a System in the World, if its parameters don't conflict with each other. If
+    /// the system accesses data mutably in one parameter and accesses it again in another
+    /// parameter (for example, `Query<&mut A>` and `Query<&A>`), it would panic while running,
+    /// so it's rejected with an `AccessConflict` instead.
+    pub fn try_add_system<S: System<In = ()> + MaybeSend + MaybeSync + 'static>(
+        &mut self,
+        system: impl IntoSystem<S>,
+    ) -> Result<(), AccessConflict>
+    where
+        S::Out: SystemOutput,
+    {
+        let system = system.into_system();
+        system.access().check(system.name())?;
+        self.push_system(self.systems.clone(), system);
+        Ok(())
+    }
+    /// Adds a system to `systems`, or queues it in `World::queued_systems` if they're running.
+    fn push_system<S: System<In = ()> + MaybeSend + MaybeSync + 'static>(
+        &mut self,
+        systems: Rc<RefCell<Systems>>,
+        system: S,
+    ) where
+        S::Out: SystemOutput,
+    {
+        if let Ok(mut systems) = systems.try_borrow_mut() {
+            systems.push(system);
+            return;
+        }
+        self.queued_systems
+            .push(Box::new(move || systems.borrow_mut().push(system)));
+    }
+    /// Adds the systems that were queued while the World's systems were running.
+    fn add_queued_systems(&mut self) {
+        for add in core::mem::take(&mut self.queued_systems) {
+            add();
+        }
+    }
+
+    /// Finds systems in the World that access the same data in conflicting ways, but aren't
+    /// ordered relative to each other. See the `system::order` module.
+    pub fn ambiguities(&self) -> Vec<Ambiguity> {
+        self.systems.borrow().ambiguities()
+    }
+
+    /// Renders the World's systems and fixed systems as a Graphviz DOT graph, each in their own
+    /// cluster. See the `system::dot` module.
+    pub fn to_dot(&self) -> String {
+        dot::clusters_to_dot(&[
+            ("systems", &self.systems.borrow()),
+            ("fixed systems", &self.fixed_systems.borrow()),
+        ])
+    }
+
+    /// Registers a one-shot system. The system won't run with the rest of the World's systems;
+    /// instead, it runs every time `World::run_system` or the `RunSystem` command is used with
+    /// the returned ID. Like systems in the World, one-shot systems may return a
+    /// `Result<(), E>`, and keep their `Local`s between runs.
+    ///
+    /// Panics if the system accesses the same data in conflicting ways, like `add_system`.
+    pub fn register_system<S: System<In = ()> + 'static>(
+        &mut self,
+        system: impl IntoSystem<S>,
+    ) -> SystemId
+    where
+        S::Out: SystemOutput,
+    {
+        let system = system.into_system();
+        if let Err(conflict) = system.access().check(system.name()) {
+            panic!("{conflict}");
+        }
+        self.registered_systems
+            .push(Some(Box::new(HandledSystem(system, None))));
+        SystemId(self.registered_systems.len() - 1)
+    }
+    /// Runs a one-shot system that was registered with `World::register_system`.
+    ///
+    /// Panics if the ID is invalid, or if the system is already running (ie, a one-shot system
+    /// tries to run itself).
+    pub fn run_system(&mut self, id: SystemId) {
+        let mut system = self
+            .registered_systems
+            .get_mut(id.0)
+            .expect("Tried to run a one-shot system with an invalid ID")
+            .take()
+            .expect("Tried to run a one-shot system while it was already running");
+        #[cfg(feature = "std")]
+        if let Err(payload) = panic::catch(self, |world| system.execute((), world)) {
+            system = Box::new(panic::record(&*system, payload, self));
+        }
+        #[cfg(not(feature = "std"))]
+        system.execute((), self);
+        self.registered_systems[id.0] = Some(system);
+    }
+
+    /// Runs a system a single time, and returns its output. The system doesn't need to be
+    /// registered in the World. Since the system is made just for this call, any `Local`s it
+    /// uses start from their default value.
+    #[inline]
+    pub fn run_system_once<S: System<In = ()>>(&mut self, system: impl IntoSystem<S>) -> S::Out {
+        self.run_system_once_with((), system)
+    }
+    /// Same as `run_system_once`, but gives the system an input. See `In`.
+    #[inline]
+    pub fn run_system_once_with<S: System>(
+        &mut self,
+        input: S::In,
+        system: impl IntoSystem<S>,
+    ) -> S::Out {
+        system.into_system().execute(input, self)
+    }
+
+    /// Register a System that runs on a fixed timestep. Fixed systems run before the World's
+    /// other systems, once for every `Time::fixed_step` that's passed. They only run if the
+    /// World has a clock; see `World::set_clock`.
+    ///
+    /// Panics if the system accesses the same data in conflicting ways, like `add_system`. Like
+    /// `add_system`, fixed systems added by an exclusive system are added after it finishes.
+    #[inline]
+    pub fn add_fixed_system<S: System<In = ()> + MaybeSend + MaybeSync + 'static>(
+        &mut self,
+        system: impl IntoSystem<S>,
+    ) where
+        S::Out: SystemOutput,
+    {
+        let system = system.into_system();
+        if let Err(conflict) = system.access().check(system.name()) {
+            panic!("{conflict}");
+        }
+        self.push_system(self.fixed_systems.clone(), system);
+    }
+
+    /// Adds a state machine to the World, starting in the `initial` state. This inserts the
+    /// `State<S>` and `NextState<S>` resources; see the `state` module for how transitions
+    /// work. Adding a state that already exists resets it to `initial`.
+    pub fn add_state<S: States>(&mut self, initial: S) {
+        state::init_state(self, initial);
+    }
+    /// Register a System that runs when a state is entered or exited, ie `OnEnter(state)` or
+    /// `OnExit(state)`. The state must have been added with `World::add_state`.
+    ///
+    /// Panics if the system accesses the same data in conflicting ways, like `add_system`.
+    pub fn add_state_system<
+        G: TransitionSystems,
+        S: System<In = ()> + MaybeSend + MaybeSync + 'static,
+    >(
+        &mut self,
+        group: G,
+        system: impl IntoSystem<S>,
+    ) where
+        S::Out: SystemOutput,
+    {
+        let system = system.into_system();
+        if let Err(conflict) = system.access().check(system.name()) {
+            panic!("{conflict}");
+        }
+        let mut groups = self
+            .get_resource_mut::<state::StateSystems<G::State>>()
+            .expect("Tried to add a state system before adding the state to the World");
+        group.systems(&mut groups).push(system);
+    }
+
+    /// Sets the clock the World reads the time from, and adds the `Time` resource if it
+    /// doesn't exist yet. Time starts from the moment this is called.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        let now = clock.now();
+        let mut time = self
+            .get_resource::<Time>()
+            .map(|time| *time)
+            .unwrap_or(Time::new(now));
+        time.last_update = now;
+        self.insert_resource(time);
+        self.clock = Some(Shared::new(clock));
+    }
+
+    /// Adds a hook that's called before and after every system runs. See the
+    /// `system::diagnostics` module.
+    pub fn add_system_hook(&mut self, hook: impl SystemHook + 'static) {
+        self.system_hooks.push(Box::new(hook));
+    }
+
+    /// Sets whether systems that panic are disabled, so the rest of the World keeps running,
+    /// instead of the panic crashing the program. This is off by default. See the
+    /// `system::panic` module.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn set_isolate_panics(&mut self, isolate: bool) {
+        self.isolate_panics = isolate;
+    }
+
+    /// Sets the function that handles errors returned by systems. The `system::error` module
+    /// has handlers to panic (the default), ignore errors, or collect them into a resource.
+    #[inline]
+    pub fn set_error_handler(&mut self, handler: ErrorHandler) {
+        self.error_handler = handler;
     }
 
     /// Runs all of the World's Systems once. This will run even if the `ExitRunLoop` command has been
     /// used.
+    ///
+    /// Pending state transitions are applied first. Then, if the World has a clock, this
+    /// updates the `Time` resource, and runs the fixed systems once for each fixed step that's
+    /// passed before running the other systems.
+    ///
+    /// If stepping is enabled, this runs from wherever the World is paused until the end of the
+    /// frame, or until the next breakpoint. See the `system::stepping` module.
     #[inline]
     pub fn run_once(&mut self) {
-        self.systems.clone().borrow().run(self);
+        let systems = self.systems.clone();
+        let mut systems = systems.borrow_mut();
+        if systems.at_frame_start() {
+            systems.apply_state_transitions(self);
+            self.run_fixed_systems();
+        }
+        systems.run(self);
+        drop(systems);
+        self.add_queued_systems();
+    }
+    /// Runs exactly one of the World's Systems, then pauses. State transitions are applied and
+    /// fixed systems run before the first system in a frame. See the `system::stepping` module.
+    ///
+    /// Panics if stepping isn't enabled.
+    pub fn step(&mut self) {
+        let systems = self.systems.clone();
+        let mut systems = systems.borrow_mut();
+        if systems.at_frame_start() {
+            systems.apply_state_transitions(self);
+            self.run_fixed_systems();
+        }
+        systems.step(self);
+        drop(systems);
+        self.add_queued_systems();
+    }
+    /// Makes the World's Systems pause after each one runs, so they can be run one at a time with
+    /// `World::step`. See the `system::stepping` module.
+    pub fn enable_stepping(&mut self) {
+        let mut systems = self.systems.borrow_mut();
+        if systems.stepping.is_none() {
+            systems.stepping = Some(Stepping::default());
+        }
+    }
+    /// Stops stepping through systems. If the World is paused in the middle of a frame, the
+    /// rest of the frame's systems run first.
+    pub fn disable_stepping(&mut self) {
+        let systems = self.systems.clone();
+        let mut systems = systems.borrow_mut();
+        while !systems.at_frame_start() {
+            systems.step(self);
+        }
+        systems.stepping = None;
+    }
+    /// The name of the system that will run next, if stepping is enabled.
+    pub fn next_system(&self) -> Option<String> {
+        self.systems.borrow().next_system().map(String::from)
+    }
+    /// Pauses before the system named `system` while stepping. See `Stepping::is_breakpoint`.
+    ///
+    /// Panics if stepping isn't enabled.
+    pub fn add_breakpoint(&mut self, system: &str) {
+        self.systems
+            .borrow_mut()
+            .stepping
+            .as_mut()
+            .expect("Tried to add a breakpoint without enabling stepping")
+            .add_breakpoint(system);
+    }
+    /// Removes a breakpoint added with `World::add_breakpoint`.
+    pub fn remove_breakpoint(&mut self, system: &str) {
+        if let Some(stepping) = &mut self.systems.borrow_mut().stepping {
+            stepping.remove_breakpoint(system);
+        }
     }
     /// Runs all of the World's Systems in a loop. The loop can be broken with the `ExitRunLoop`
     /// command; however, calling this method again after exiting will restart the loop until
     /// `ExitRunLoop` is called again.
-    pub fn run(&mut self) {
+    ///
+    /// If the loop was stopped with the `ExitRunLoopWith` command, this returns the command's
+    /// value.
+    pub fn run(&mut self) -> Option<Box<AnyData>> {
+        self.run_until(|_| false)
+    }
+    /// Runs all of the World's Systems at most `runs` times. Like `World::run`, this stops early
+    /// if the `ExitRunLoop` command is used, and returns the `ExitRunLoopWith` value if there
+    /// was one.
+    pub fn run_for(&mut self, runs: usize) -> Option<Box<AnyData>> {
+        let mut finished = 0;
+        self.run_until(|_| {
+            finished += 1;
+            finished > runs
+        })
+    }
+    /// Runs all of the World's Systems in a loop until `done` returns true. `done` is checked
+    /// before every run, so the systems won't run at all if it's already true. Like
+    /// `World::run`, this stops early if the `ExitRunLoop` command is used, and returns the
+    /// `ExitRunLoopWith` value if there was one.
+    pub fn run_until(&mut self, mut done: impl FnMut(&World) -> bool) -> Option<Box<AnyData>> {
         self.exit_run_loop = false;
-        let systems = self.systems.clone();
-        let systems = systems.borrow();
+        self.exit_value = None;
 
-        while !self.exit_run_loop {
-            systems.run(self);
+        while !self.exit_run_loop && !done(self) {
+            self.run_once();
+        }
+
+        self.exit_value.take()
+    }
+    /// Reads the clock and updates the `Time` resource. Returns the number of fixed steps that
+    /// should run this frame.
+    fn update_time(&mut self) -> u32 {
+        let Some(clock) = &self.clock else {
+            return 0;
+        };
+        let now = clock.now();
+        match self.get_resource_mut::<Time>() {
+            Some(mut time) => time.update(now),
+            None => 0,
         }
     }
 
+    /// Updates the `Time` resource, then runs the fixed systems once for each fixed step that's
+    /// passed.
+    fn run_fixed_systems(&mut self) {
+        let fixed_steps = self.update_time();
+        if fixed_steps > 0 {
+            let fixed_systems = self.fixed_systems.clone();
+            let mut fixed_systems = fixed_systems.borrow_mut();
+            for _ in 0..fixed_steps {
+                fixed_systems.run(self);
+            }
+        }
+    }
+
+    /// Sets when commands from the World's main systems are applied. The fixed systems and
+    /// state systems have their own modes; see `Systems::set_command_mode`.
+    #[inline]
+    pub fn set_command_mode(&mut self, mode: CommandMode) {
+        self.systems.borrow_mut().set_command_mode(mode);
+    }
+    /// Applies every command that was deferred because of the `Deferred` command mode, by the
+    /// `Systems` that are currently running - or, if none are, by the World's main systems.
+    pub fn apply_deferred(&mut self) {
+        let buffer = match &self.active_commands {
+            Some(buffer) => buffer.clone(),
+            None => self.systems.borrow().commands.clone(),
+        };
+        // Commands can run systems, which may defer even more commands
+        loop {
+            let commands = core::mem::take(&mut buffer.borrow_mut().deferred);
+            if commands.is_empty() {
+                break;
+            }
+            self.apply_commands(commands);
+        }
+    }
     /// Applies changes from a command queue to the world.
     pub fn apply_commands(&mut self, commands: Vec<Command>) {
         for command in commands {
@@ -127,6 +515,11 @@ impl World {
                 }
                 Command::DespawnEntity(id) => self.storage.despawn(id),
                 Command::ExitRunLoop => self.exit_run_loop = true,
+                Command::ExitRunLoopWith(value) => {
+                    self.exit_run_loop = true;
+                    self.exit_value = Some(value);
+                }
+                Command::RunSystem(id) => self.run_system(id),
             };
         }
     }
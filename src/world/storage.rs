@@ -1,169 +1,497 @@
 //! The storage backend for SECS, which stores all of the resources and entities
 //! in the world.
 //!
-//! It might make more sense to picture the World like this:
+//! Entities are grouped by the components they have, and each group is stored in a `Table` (see
+//! the `table` module). It might make more sense to picture the World like this:
 //!
 //!```txt
-//!                 Entity 1       Entity 2       Entity 3
-//! Component1:   Some(<data>)       None           None
-//! Component2:   Some(<data>)       None       Some(<data>)
-//! Component3:   Some(<data>)   Some(<data>)       None
+//! Table 0 (no components):     Entity 4
+//! Table 1 (Component1):        Entity 2       Entity 5
+//! Table 2 (Component1 + 2):    Entity 1       Entity 3
+//!                                 |              |
+//!                 Component1:   <data>         <data>
+//!                 Component2:   <data>         <data>
 //! ```
 //!
-//! In this layout, the whole table is the World, each row is an Archetype, each column is an
-//! Entity, and each table item is a Component.
+//! Each table has a column for each of its components, and a row for each of its entities. The
+//! storage tracks which table and row each entity is in with an `EntityLocation`.
+//!
+//! Tables and columns only grow when an entity is added to them, so spawning an entity only
+//! touches the table for its components - it doesn't matter how many other components or tables
+//! exist. `spawn_with` puts new entities straight into their table, instead of spawning them into
+//! the empty table and moving them.
+//!
+//! Bundles whose type is known (see `spawn_bundle` and `insert_bundle`) skip boxing their
+//! components: each component is written straight into its column or sparse set. The table a
+//! bundle moves an entity to only depends on the entity's old table and the bundle's type, so
+//! it's cached in `bundle_tables`.
+//!
+//! Components can also be stored in sparse sets instead of tables (see the `sparse_set` module
+//! and `StorageType`). Sparse set components aren't part of an entity's table.
 
 use {
-    crate::_crate_prelude::*,
-    alloc::rc::Rc,
-    core::{
-        cell::RefCell,
-        hash::{BuildHasher, Hasher},
-        ops::Deref,
+    crate::{
+        _crate_prelude::*,
+        entity::{Bundle, StorageType},
+        sync::{AnyData, Lock, MaybeSend, MaybeSync, Shared},
     },
+    alloc::collections::BTreeMap,
+    core::hash::{BuildHasher, Hasher},
     hashbrown::HashMap,
+    sparse_set::{ComponentSparseSet, SparseSet},
+    table::{ColumnBuilder, ComponentColumn, Table},
 };
 
-/// Archetypes store components in the world. There's an archetype for each
-/// type of component in the world. Each archetype stores one type of component,
-/// and stores that type for every single entity.
-///
-/// This is a trait for archetypes: the struct that actually stores components is
-/// `WorldArchetype`. However, that struct is generic, so it can't be stored in
-/// the world (each archetype has a different generic for the type of component
-/// it stores). This trait is not generic - it "type-erases" the archetype, providing
-/// useful functions for all archetypes without having to handle each individual
-/// archetype's component type.
-pub trait Archetype: AsAny {
-    /// Update an entity's component.
-    fn set(&mut self, entity: usize, component: Box<dyn Component>);
-    /// Get an entity's component.
-    fn get_component(&self, entity: usize) -> Option<Rc<RefCell<dyn Component>>>;
-
-    /// Removes the component this archetype stores for an entity.
-    fn despawn(&mut self, entity: usize);
-
-    /// Tells the archetype a new entity has spawned, so it can allocate space for it.
-    fn add_entity(&mut self);
-
-    /// The `TypeId` of the component this Archetype stores.
-    fn contained_type_id(&self) -> TypeId;
-}
+pub mod sparse_set;
+pub mod table;
 
-/// The struct that actually stores components for an archetype. See the `Archetype`
-/// trait for more info.
-#[derive(Debug)]
-pub struct WorldArchetype<C: Component> {
-    components: Vec<Option<Rc<RefCell<C>>>>,
-}
-impl<C: Component> WorldArchetype<C> {
-    /// Make a new Archetype with a specific starting size.
-    pub fn new_with_size(size: usize) -> Self {
-        let mut components = Vec::with_capacity(size);
-        (0..size).for_each(|_| components.push(None));
+/// The table for entities without any components. New entities start in this table.
+pub const EMPTY_TABLE: usize = 0;
 
-        Self { components }
-    }
-}
-impl<C: Component> Archetype for WorldArchetype<C> {
-    fn set(&mut self, entity: usize, component: Box<dyn Component>) {
-        self.components[entity] = Some(Rc::new(RefCell::new(
-            *component
-                .as_any_owned()
-                .downcast()
-                .expect("TODO: Error handling. Component was inserted into the wrong archetype."),
-        )))
-    }
-    fn get_component(&self, entity: usize) -> Option<Rc<RefCell<dyn Component>>> {
-        self.components[entity]
-            .as_ref()
-            .cloned()
-            .map(|component| component as Rc<RefCell<dyn Component>>)
-    }
-
-    fn despawn(&mut self, entity: usize) {
-        self.components[entity] = None;
-    }
-
-    fn add_entity(&mut self) {
-        self.components.push(None);
-    }
-
-    fn contained_type_id(&self) -> TypeId {
-        TypeId::of::<C>()
-    }
+/// Where an entity's components are stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityLocation {
+    /// The index of the entity's table, in `Storage::tables`.
+    pub table: usize,
+    /// The entity's row in that table.
+    pub row: usize,
 }
 
 /// This is the actual backend that stores all the entities and resources in the world.
-#[derive(Default)]
 pub struct Storage {
-    /// All of the `Archetype`s that make up the `World`. Archetypes store the components that make
-    /// up entities.
-    pub archetypes: HashMap<TypeId, Rc<RefCell<dyn Archetype>>, TypeHasherBuilder>,
+    /// All of the `Table`s that make up the `World`. Tables store the components that make up
+    /// entities.
+    pub tables: Vec<Table>,
+    /// The index of the table for each set of components. Each set is sorted by `TypeId`.
+    pub table_ids: BTreeMap<Vec<TypeId>, usize>,
+    /// The table an entity moves to when a bundle is inserted into it, by the entity's old table
+    /// and the bundle's `TypeId`. See `insert_bundle`.
+    pub bundle_tables: BTreeMap<(usize, TypeId), usize>,
+    /// Functions that make an empty `Column` for a component. These are added by
+    /// `Component::prep_storage`, so tables can make columns for components without knowing
+    /// their types.
+    pub column_builders: HashMap<TypeId, ColumnBuilder, TypeHasherBuilder>,
+    /// The sparse set for each component stored in one. See the `sparse_set` module.
+    pub sparse_sets: HashMap<TypeId, Box<dyn SparseSet>, TypeHasherBuilder>,
+    /// Where each entity is stored, by its ID. Despawned entities are `None`.
+    pub locations: Vec<Option<EntityLocation>>,
     /// All of the `Resource`s stored in the `World`. Each resource is stored by its type, so
     /// there can't be two resources of the same type.
-    pub resources: HashMap<TypeId, Rc<RefCell<dyn Any>>, TypeHasherBuilder>,
+    pub resources: HashMap<TypeId, Shared<Lock<AnyData>>, TypeHasherBuilder>,
     /// The number of entities that have existed in the world. This number is never decremented,
     /// even when entities are despawned; it does not track the number of entities currently in
     /// the world, but rather how many entities have existed, and what the next entity's ID
     /// would be.
     pub num_entities: usize,
 }
+impl Default for Storage {
+    fn default() -> Self {
+        let mut storage = Self {
+            tables: Vec::new(),
+            table_ids: BTreeMap::new(),
+            bundle_tables: BTreeMap::new(),
+            column_builders: HashMap::default(),
+            sparse_sets: HashMap::default(),
+            locations: Vec::new(),
+            resources: HashMap::default(),
+            num_entities: 0,
+        };
+        storage.table_for(Vec::new());
+        storage
+    }
+}
 impl Storage {
-    /// Get the archetype for a particular component, by that component's `TypeId`.
-    pub fn get_archetype(&self, id: TypeId) -> Option<Rc<RefCell<dyn Archetype>>> {
-        self.archetypes.get(&id).cloned()
+    /// Get the index of the table for a set of components, which must be sorted by `TypeId`.
+    /// The table is made if it doesn't exist yet.
+    pub fn table_for(&mut self, components: Vec<TypeId>) -> usize {
+        if let Some(table) = self.table_ids.get(&components) {
+            return *table;
+        }
+
+        let columns = components
+            .iter()
+            .map(|component| {
+                let builder = self.column_builders.get(component).expect(
+                    "Tried to make a table for a component that wasn't prepared with `prep_for`",
+                );
+                builder()
+            })
+            .collect();
+        self.tables.push(Table {
+            components: components.clone(),
+            columns,
+            entities: Vec::new(),
+        });
+        self.table_ids.insert(components, self.tables.len() - 1);
+        self.tables.len() - 1
+    }
+
+    /// Where an entity is stored, if it exists.
+    pub fn location(&self, entity: usize) -> Option<EntityLocation> {
+        self.locations.get(entity).copied().flatten()
+    }
+    /// Find one of an entity's components, by the component's `TypeId`. This returns the column
+    /// (or sparse set) the component is stored in, and the component's index in it. See
+    /// `Column::data`.
+    pub fn get_component(
+        &self,
+        entity: usize,
+        component: TypeId,
+    ) -> Option<(Shared<Lock<AnyData>>, usize)> {
+        if let Some(sparse_set) = self.sparse_sets.get(&component) {
+            return Some((sparse_set.data(), sparse_set.index(entity)?));
+        }
+        let location = self.location(entity)?;
+        self.tables[location.table]
+            .column(component)
+            .map(|column| (column.data(), location.row))
+    }
+
+    /// Finds every entity with all of `components`. Returns those entities, and a
+    /// `ComponentFetch` for each of the components that says where to find them. Queries use
+    /// this to get their components.
+    ///
+    /// If none of the components are stored in sparse sets, this only visits tables with all of
+    /// the components. Otherwise, it visits every entity in the first sparse set, and looks up
+    /// the rest of their components.
+    pub fn query(&self, components: &[TypeId]) -> (Vec<usize>, Vec<ComponentFetch>) {
+        let mut entities = Vec::new();
+        let mut fetches: Vec<ComponentFetch> = components
+            .iter()
+            .map(|_| ComponentFetch::default())
+            .collect();
+
+        if let Some(sparse_set) = components
+            .iter()
+            .find_map(|component| self.sparse_sets.get(component))
+        {
+            for entity in sparse_set.entities() {
+                let found: Option<Vec<_>> = components
+                    .iter()
+                    .map(|component| self.get_component(*entity, *component))
+                    .collect();
+                let Some(found) = found else {
+                    continue;
+                };
+                entities.push(*entity);
+                for (fetch, (column, row)) in fetches.iter_mut().zip(found) {
+                    fetch.push(column, row);
+                }
+            }
+            return (entities, fetches);
+        }
+
+        for table in &self.tables {
+            if table.is_empty() || !table.contains_all(components) {
+                continue;
+            }
+            entities.extend_from_slice(&table.entities);
+            for (fetch, component) in fetches.iter_mut().zip(components) {
+                fetch.columns.push(table.column(*component).unwrap().data());
+                let column = fetch.columns.len() - 1;
+                fetch.rows.extend((0..table.len()).map(|row| (column, row)));
+            }
+        }
+        (entities, fetches)
     }
 
     /// Set a component for an entity. This will overwrite an existing component, if there
     /// is one.
     pub fn insert_component(&mut self, entity: usize, component: impl Component) {
-        self.insert_component_boxed(entity, Box::new(component))
+        self.insert_components_boxed(entity, vec![Box::new(component)])
     }
     /// Insert a component that's been boxed. This will overwrite an existing component, if
     /// there is one.
     pub fn insert_component_boxed(&mut self, entity: usize, component: Box<dyn Component>) {
-        self.get_archetype((*component).type_id())
-            .expect("TODO: Error handling. Archetype for component didn't exist when trying to insert component.")
-            .deref()
-            .borrow_mut()
-            .set(entity, component)
+        self.insert_components_boxed(entity, vec![component])
     }
+    /// Insert several boxed components into an entity at once, overwriting any components it
+    /// already has of the same types. The entity only moves to a new table once.
+    ///
+    /// Panics if the entity doesn't exist.
+    pub fn insert_components_boxed(&mut self, entity: usize, components: Vec<Box<dyn Component>>) {
+        let location = self
+            .location(entity)
+            .expect("Tried to insert components into an entity that doesn't exist");
 
-    /// Ensure the storage has an archetype for a particular component.
+        let mut types = self.tables[location.table].components.clone();
+        let table_components = self.insert_sparse_components(entity, components, &mut types);
+        let table = self.table_for(types);
+        let row = self.move_entity(entity, table);
+        self.write_row(table, row, table_components);
+    }
+    /// Insert a bundle into an entity, overwriting any components it already has of the same
+    /// types. Unlike `insert_components_boxed`, the components aren't boxed, and the entity's new
+    /// table is cached for the bundle's type.
+    ///
+    /// Panics if the entity doesn't exist.
+    pub fn insert_bundle<B: Bundle>(&mut self, entity: usize, bundle: B) {
+        let location = self
+            .location(entity)
+            .expect("Tried to insert components into an entity that doesn't exist");
+        let table = self.bundle_table::<B>(location.table);
+        let row = self.move_entity(entity, table);
+        bundle.write(entity, EntityLocation { table, row }, self);
+    }
+    /// The table an entity in `from` moves to when a `B` is inserted into it. This is cached in
+    /// `bundle_tables`, so the bundle's components are only prepared the first time.
+    fn bundle_table<B: Bundle>(&mut self, from: usize) -> usize {
+        let key = (from, TypeId::of::<B>());
+        if let Some(table) = self.bundle_tables.get(&key) {
+            return *table;
+        }
+
+        let mut types = self.tables[from].components.clone();
+        B::prep_storage(self, &mut types);
+        types.sort();
+        types.dedup();
+        let table = self.table_for(types);
+        self.bundle_tables.insert(key, table);
+        table
+    }
+    /// Writes a component straight into its storage, without boxing it. For table components,
+    /// the entity must already be in `location`, and the column is allowed to be one row short
+    /// (see `move_entity`).
+    pub fn write_component<C: Component>(
+        &mut self,
+        entity: usize,
+        location: EntityLocation,
+        component: C,
+    ) {
+        let component_type = TypeId::of::<C>();
+        match C::storage_type() {
+            StorageType::Table => {
+                let column: &mut ComponentColumn<C> = self.tables[location.table]
+                    .column_mut(component_type)
+                    .and_then(|column| column.as_any_mut().downcast_mut())
+                    .expect("Tried to write a component into a table without its column");
+                column.write(location.row, component);
+            }
+            StorageType::SparseSet => {
+                let sparse_set: &mut ComponentSparseSet<C> = self
+                    .sparse_sets
+                    .get_mut(&component_type)
+                    .and_then(|sparse_set| (**sparse_set).as_any_mut().downcast_mut())
+                    .expect("Tried to write a component that wasn't prepared with `prep_for`");
+                sparse_set.set(entity, component);
+            }
+        }
+    }
+    /// Inserts components that are stored in sparse sets into the entity, and returns the rest.
+    /// The `TypeId`s of the returned components are added to `types`, which is then sorted, so
+    /// it can be passed to `table_for`.
+    fn insert_sparse_components(
+        &mut self,
+        entity: usize,
+        components: Vec<Box<dyn Component>>,
+        types: &mut Vec<TypeId>,
+    ) -> Vec<Box<dyn Component>> {
+        let mut table_components = Vec::with_capacity(components.len());
+        for component in components {
+            component.prep_storage(self);
+            match self.sparse_sets.get_mut(&(*component).type_id()) {
+                Some(sparse_set) => sparse_set.insert(entity, component),
+                None => {
+                    types.push((*component).type_id());
+                    table_components.push(component);
+                }
+            }
+        }
+        types.sort();
+        types.dedup();
+        table_components
+    }
+    /// Writes components into a row of a table. Columns that are one row short (see
+    /// `move_entity`) get the component pushed; the rest have it overwritten.
+    fn write_row(&mut self, table: usize, row: usize, components: Vec<Box<dyn Component>>) {
+        let table = &mut self.tables[table];
+        for component in components {
+            let column = table.column_mut((*component).type_id()).unwrap();
+            if column.len() > row {
+                column.set(row, component);
+            } else {
+                column.push(component);
+            }
+        }
+    }
+    /// Removes one of an entity's components, by the component's `TypeId`. This does nothing if
+    /// the entity doesn't have the component.
+    pub fn remove_component(&mut self, entity: usize, component: TypeId) {
+        if let Some(sparse_set) = self.sparse_sets.get_mut(&component) {
+            sparse_set.remove(entity);
+            return;
+        }
+        let Some(location) = self.location(entity) else {
+            return;
+        };
+        let mut types = self.tables[location.table].components.clone();
+        let Ok(idx) = types.binary_search(&component) else {
+            return;
+        };
+        types.remove(idx);
+
+        let table = self.table_for(types);
+        self.move_entity(entity, table);
+    }
+
+    /// Ensure the storage can store a particular component, in a table or a sparse set depending
+    /// on its `StorageType`.
     pub fn prep_for<C: Component>(&mut self) {
-        self.archetypes.entry(TypeId::of::<C>()).or_insert_with(|| {
-            Rc::new(RefCell::new(WorldArchetype::<C>::new_with_size(
-                self.num_entities,
-            )))
-        });
+        match C::storage_type() {
+            StorageType::Table => {
+                self.column_builders
+                    .entry(TypeId::of::<C>())
+                    .or_insert(ComponentColumn::<C>::new_boxed);
+            }
+            StorageType::SparseSet => {
+                self.sparse_sets
+                    .entry(TypeId::of::<C>())
+                    .or_insert_with(|| Box::<ComponentSparseSet<C>>::default());
+            }
+        }
     }
 
     /// Insert a resouce into the world. This will overwrite a resource of the same type,
     /// if one already exists.
-    pub fn insert_resource(&mut self, resource: impl Any) {
+    pub fn insert_resource(&mut self, resource: impl Any + MaybeSend + MaybeSync) {
         self.resources
-            .insert(resource.type_id(), Rc::new(RefCell::new(resource)) as _);
+            .insert(resource.type_id(), Shared::new(Lock::new(resource)) as _);
     }
     /// Get a resource by its `TypeId`.
-    pub fn get_resource(&self, id: TypeId) -> Option<Rc<RefCell<dyn Any>>> {
+    pub fn get_resource(&self, id: TypeId) -> Option<Shared<Lock<AnyData>>> {
         self.resources.get(&id).cloned()
     }
 
-    /// Spawn a new entity and return its ID.
+    /// Spawn a new entity, without any components, and return its ID.
     pub fn spawn(&mut self) -> usize {
-        for archetype in self.archetypes.values() {
-            archetype.borrow_mut().add_entity()
-        }
+        let entity = self.num_entities;
+        let table = &mut self.tables[EMPTY_TABLE];
+        table.entities.push(entity);
+        self.locations.push(Some(EntityLocation {
+            table: EMPTY_TABLE,
+            row: table.len() - 1,
+        }));
+
+        self.num_entities += 1;
+        entity
+    }
+    /// Spawn a new entity with a bundle of components, and return its ID. Like `spawn_with`, the
+    /// entity goes straight into its table, and like `insert_bundle`, the components aren't
+    /// boxed.
+    pub fn spawn_bundle<B: Bundle>(&mut self, bundle: B) -> usize {
+        let entity = self.num_entities;
+        self.num_entities += 1;
+
+        let table = self.bundle_table::<B>(EMPTY_TABLE);
+        self.tables[table].entities.push(entity);
+        let location = EntityLocation {
+            table,
+            row: self.tables[table].len() - 1,
+        };
+        self.locations.push(Some(location));
+        bundle.write(entity, location, self);
 
+        entity
+    }
+    /// Spawn a new entity with some components, and return its ID. The entity goes straight into
+    /// the table for its components.
+    pub fn spawn_with(&mut self, components: Vec<Box<dyn Component>>) -> usize {
+        let entity = self.num_entities;
         self.num_entities += 1;
-        self.num_entities - 1
+        self.locations.push(None);
+
+        let mut types = Vec::with_capacity(components.len());
+        let table_components = self.insert_sparse_components(entity, components, &mut types);
+        let table = self.table_for(types);
+        self.tables[table].entities.push(entity);
+        let row = self.tables[table].len() - 1;
+        self.locations[entity] = Some(EntityLocation { table, row });
+        self.write_row(table, row, table_components);
+
+        entity
     }
-    /// Despawn an entity by its ID.
+    /// Despawn an entity by its ID. This does nothing if the entity was already despawned.
+    ///
+    /// This checks every sparse set for the entity's components, so it gets slower the more
+    /// sparse set components there are.
     pub fn despawn(&mut self, entity: usize) {
-        self.archetypes
-            .values()
-            .for_each(|archetype| archetype.deref().borrow_mut().despawn(entity))
+        let Some(location) = self.location(entity) else {
+            return;
+        };
+        for sparse_set in self.sparse_sets.values_mut() {
+            sparse_set.remove(entity);
+        }
+        let table = &mut self.tables[location.table];
+        for column in &mut table.columns {
+            column.swap_remove(location.row);
+        }
+        table.entities.swap_remove(location.row);
+        if let Some(moved) = table.entities.get(location.row) {
+            self.locations[*moved] = Some(location);
+        }
+        self.locations[entity] = None;
+    }
+
+    /// Moves an entity to another table, and returns its row in that table. Components the new
+    /// table doesn't have are dropped. The new table's columns for components the entity didn't
+    /// have are left one row short, so the caller has to push those components.
+    fn move_entity(&mut self, entity: usize, table: usize) -> usize {
+        let location = self.location(entity).unwrap();
+        if location.table == table {
+            return location.row;
+        }
+
+        let (old, new) = if location.table < table {
+            let (left, right) = self.tables.split_at_mut(table);
+            (&mut left[location.table], &mut right[0])
+        } else {
+            let (left, right) = self.tables.split_at_mut(location.table);
+            (&mut right[0], &mut left[table])
+        };
+
+        for (component, column) in old.components.iter().zip(&mut old.columns) {
+            match new.column_mut(*component) {
+                Some(new_column) => column.move_row(location.row, new_column),
+                None => column.swap_remove(location.row),
+            }
+        }
+        old.entities.swap_remove(location.row);
+        if let Some(moved) = old.entities.get(location.row) {
+            self.locations[*moved] = Some(location);
+        }
+
+        new.entities.push(entity);
+        let row = new.len() - 1;
+        self.locations[entity] = Some(EntityLocation { table, row });
+        row
+    }
+}
+
+/// Where a query's components of one type are stored. See `Storage::query`.
+#[derive(Default)]
+pub struct ComponentFetch {
+    /// Every column (or sparse set) the components are stored in. Each column is only listed
+    /// once.
+    pub columns: Vec<Shared<Lock<AnyData>>>,
+    /// The index in `columns` of each entity's component, and the component's row in that
+    /// column. These are in the same order as the query's entities.
+    pub rows: Vec<(usize, usize)>,
+}
+impl ComponentFetch {
+    /// Adds the component stored in `column` at `row`.
+    fn push(&mut self, column: Shared<Lock<AnyData>>, row: usize) {
+        let idx = match self
+            .columns
+            .iter()
+            .position(|other| Shared::ptr_eq(other, &column))
+        {
+            Some(idx) => idx,
+            None => {
+                self.columns.push(column);
+                self.columns.len() - 1
+            }
+        };
+        self.rows.push((idx, row));
     }
 }
 
@@ -197,3 +525,93 @@ impl BuildHasher for TypeHasherBuilder {
         TypeHasher(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{self as secs, _crate_prelude::*, prelude::*};
+
+    #[derive(Component)]
+    struct Position(u32);
+    #[derive(Component)]
+    struct Velocity(u32);
+
+    fn positions(world: &mut World) -> Vec<(usize, u32)> {
+        world.run_system_once(|query: &Query<&Position>| {
+            let mut positions: Vec<_> = query
+                .iter_with_entity()
+                .map(|(entity, position)| (entity, position.0))
+                .collect();
+            positions.sort();
+            positions
+        })
+    }
+
+    /// Verifies that entities move between tables as components are added and removed, and
+    /// keep their other components.
+    #[test]
+    fn table_moves() {
+        let mut world = World::default();
+        let first = world.spawn(Position(1));
+        let second = world.spawn((Position(2), Velocity(2)));
+        let third = world.spawn(Position(3));
+        assert_eq!(world.storage.tables.len(), 3);
+
+        world.insert_components(first, Velocity(1));
+        assert_eq!(
+            world.storage.location(first).unwrap().table,
+            world.storage.location(second).unwrap().table
+        );
+        // The third entity was moved into the first one's old row
+        assert_eq!(world.storage.location(third).unwrap().row, 0);
+
+        world.remove_component::<Velocity>(second);
+        world.storage.despawn(third);
+        assert_eq!(positions(&mut world), [(first, 1), (second, 2)]);
+
+        let velocities = world.run_system_once(|query: &Query<(&Position, &Velocity)>| {
+            query
+                .iter()
+                .map(|(_, velocity)| velocity.0)
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(velocities, [1]);
+    }
+
+    /// Verifies that spawning an entity only adds it to the table for its components.
+    #[test]
+    fn spawn_into_table() {
+        let mut world = World::default();
+        let first = world.spawn(Velocity(1));
+        let entity = world.spawn((Position(1), Position(2)));
+        let table = world.storage.location(entity).unwrap().table;
+
+        let lengths: Vec<_> = world
+            .storage
+            .tables
+            .iter()
+            .map(|table| table.len())
+            .collect();
+        let mut expected = vec![0; world.storage.tables.len()];
+        expected[table] = 1;
+        expected[world.storage.location(first).unwrap().table] = 1;
+        assert_eq!(lengths, expected);
+        // The second `Position` overwrote the first
+        assert_eq!(positions(&mut world), [(entity, 2)]);
+    }
+
+    /// Verifies that the table a bundle moves entities to is cached by the bundle's type.
+    #[test]
+    fn bundle_tables() {
+        let mut world = World::default();
+        let first = world.spawn((Position(1), Velocity(1)));
+        let second = world.spawn((Velocity(2), Position(2)));
+        let third = world.spawn(Position(3));
+        world.insert_components(third, Velocity(3));
+
+        let table = world.storage.location(first).unwrap().table;
+        assert_eq!(world.storage.location(second).unwrap().table, table);
+        assert_eq!(world.storage.location(third).unwrap().table, table);
+        assert_eq!(world.storage.bundle_tables.len(), 4);
+        assert_eq!(positions(&mut world), [(first, 1), (second, 2), (third, 3)]);
+    }
+}
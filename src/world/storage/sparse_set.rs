@@ -0,0 +1,174 @@
+//! Sparse sets, an alternative to tables for components that are added and removed often.
+//!
+//! Moving an entity between tables (see the `table` module) means moving every one of its
+//! components, so adding or removing a component from an entity gets slower the more components
+//! it has. Components that are stored in a sparse set aren't part of an entity's table, so adding
+//! and removing them never moves the entity. The tradeoff is that queries with sparse set
+//! components have to look up each entity's components, instead of iterating over tables row by
+//! row.
+//!
+//! Components can be stored in sparse sets with `#[component(storage = "SparseSet")]`:
+//!
+//! ```rs
+//! #[derive(Component)]
+//! #[component(storage = "SparseSet")]
+//! struct Selected;
+//! ```
+//!
+//! Each sparse set stores its components in a dense array, with the entity for each component
+//! in a second array. Like a table column, the dense array is a single `Vec` behind one `Lock`.
+//! A sparse index maps entity IDs to positions in the dense array. The index is split into
+//! pages of `PAGE_SIZE` entities, and pages are only allocated once an entity in them gets the
+//! component, so a sparse set's memory depends on how many entities have its component rather
+//! than on how many entities exist.
+
+use crate::{
+    _crate_prelude::*,
+    sync::{AnyData, Lock, Shared},
+};
+
+/// The number of entities in each page of a sparse set's index.
+pub const PAGE_SIZE: usize = 64;
+
+/// Stores one type of component, for only the entities that have it. This is a trait so the
+/// storage can keep sparse sets for different types of components; the struct that actually
+/// stores components is `ComponentSparseSet`.
+pub trait SparseSet: AsAny {
+    /// Sets an entity's component, overwriting it if the entity already has one.
+    fn insert(&mut self, entity: usize, component: Box<dyn Component>);
+    /// The index of an entity's component in `data`, if it has one.
+    fn index(&self, entity: usize) -> Option<usize>;
+    /// The set's dense array of components, as a type-erased `Vec<C>`. See `Column::data`.
+    fn data(&self) -> Shared<Lock<AnyData>>;
+    /// Removes and drops an entity's component, if it has one.
+    fn remove(&mut self, entity: usize);
+    /// Every entity with this component.
+    fn entities(&self) -> &[usize];
+}
+
+/// The struct that actually stores components in a sparse set. See the `SparseSet` trait.
+#[derive(Debug)]
+pub struct ComponentSparseSet<C: Component> {
+    /// The components in the set, in no particular order.
+    dense: Shared<Lock<Vec<C>>>,
+    /// The entity each component in `dense` belongs to.
+    entities: Vec<usize>,
+    /// Each entity's position in `dense`, split into pages.
+    sparse: Vec<Option<Box<[Option<usize>; PAGE_SIZE]>>>,
+}
+impl<C: Component> Default for ComponentSparseSet<C> {
+    fn default() -> Self {
+        Self {
+            dense: Shared::new(Lock::new(Vec::new())),
+            entities: Vec::new(),
+            sparse: Vec::new(),
+        }
+    }
+}
+impl<C: Component> ComponentSparseSet<C> {
+    /// Sets an entity's component, overwriting it if the entity already has one.
+    pub fn set(&mut self, entity: usize, component: C) {
+        match self.dense_idx(entity) {
+            Some(idx) => self.dense.borrow_mut()[idx] = component,
+            None => {
+                self.dense.borrow_mut().push(component);
+                self.entities.push(entity);
+                *self.sparse_slot(entity) = Some(self.entities.len() - 1);
+            }
+        }
+    }
+
+    /// An entity's position in `dense`, if it has this component.
+    fn dense_idx(&self, entity: usize) -> Option<usize> {
+        self.sparse
+            .get(entity / PAGE_SIZE)?
+            .as_ref()?
+            .get(entity % PAGE_SIZE)
+            .copied()
+            .flatten()
+    }
+    /// Mutable access to an entity's slot in the sparse index, allocating its page if needed.
+    fn sparse_slot(&mut self, entity: usize) -> &mut Option<usize> {
+        let page = entity / PAGE_SIZE;
+        if self.sparse.len() <= page {
+            self.sparse.resize_with(page + 1, || None);
+        }
+        &mut self.sparse[page].get_or_insert_with(|| Box::new([None; PAGE_SIZE]))
+            [entity % PAGE_SIZE]
+    }
+}
+impl<C: Component> SparseSet for ComponentSparseSet<C> {
+    fn insert(&mut self, entity: usize, component: Box<dyn Component>) {
+        let component: C = *component
+            .as_any_owned()
+            .downcast()
+            .expect("Component was inserted into the wrong sparse set");
+        self.set(entity, component);
+    }
+    fn index(&self, entity: usize) -> Option<usize> {
+        self.dense_idx(entity)
+    }
+    fn data(&self) -> Shared<Lock<AnyData>> {
+        self.dense.clone()
+    }
+    fn remove(&mut self, entity: usize) {
+        let Some(idx) = self.dense_idx(entity) else {
+            return;
+        };
+        *self.sparse_slot(entity) = None;
+        self.dense.borrow_mut().swap_remove(idx);
+        self.entities.swap_remove(idx);
+        // The last component was moved into the removed one's place
+        if let Some(&moved) = self.entities.get(idx) {
+            *self.sparse_slot(moved) = Some(idx);
+        }
+    }
+    fn entities(&self) -> &[usize] {
+        &self.entities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{self as secs, _crate_prelude::*, prelude::*};
+
+    #[derive(Component)]
+    struct Health(u32);
+    #[derive(Component)]
+    #[component(storage = "SparseSet")]
+    struct Poisoned(u32);
+
+    fn poison_damage(world: &mut World) -> Vec<(usize, u32)> {
+        world.run_system_once(|query: &Query<(&Health, &Poisoned)>| {
+            let mut damage: Vec<_> = query
+                .iter_with_entity()
+                .map(|(entity, (health, poison))| (entity, health.0 - poison.0))
+                .collect();
+            damage.sort();
+            damage
+        })
+    }
+
+    /// Verifies that sparse set components can be added, removed, and queried alongside table
+    /// components, without moving entities between tables.
+    #[test]
+    fn sparse_sets() {
+        let mut world = World::default();
+        let first = world.spawn(Health(10));
+        let second = world.spawn((Health(20), Poisoned(5)));
+        let table = world.storage.location(first).unwrap().table;
+        assert_eq!(world.storage.location(second).unwrap().table, table);
+
+        world.insert_components(first, Poisoned(1));
+        assert_eq!(world.storage.location(first).unwrap().table, table);
+        assert_eq!(poison_damage(&mut world), [(first, 9), (second, 15)]);
+
+        world.remove_component::<Poisoned>(second);
+        world.spawn(Poisoned(3));
+        assert_eq!(poison_damage(&mut world), [(first, 9)]);
+
+        world.storage.despawn(first);
+        let poisoned = world.run_system_once(|query: &Query<&Poisoned>| query.len());
+        assert_eq!(poisoned, 1);
+    }
+}
@@ -0,0 +1,154 @@
+//! Tables, which store every entity with a particular set of components.
+//!
+//! Tables are SECS' archetypes: every entity with exactly the components `A`, `B`, and `C` is
+//! stored in the same table, which has one `Column` for each of those components. An entity's
+//! components are all stored in the same row of its table's columns. When a component is added
+//! to or removed from an entity, the entity moves to the table for its new set of components.
+//!
+//! Since tables only store entities that have all of their components, a query can skip entire
+//! tables that don't match it, and iterate over the rest row by row.
+//!
+//! Each column stores its components inline, in a single `Vec` behind one `Lock`, so spawning an
+//! entity doesn't allocate for each of its components, and iterating over a column reads
+//! components that are next to each other in memory. The downside is that borrows are tracked for
+//! the whole column: a query can't hold two mutable borrows of the same component at once, even
+//! for different entities.
+
+use crate::{
+    _crate_prelude::*,
+    sync::{AnyData, Lock, Shared},
+};
+
+/// Stores one type of component for every entity in a table. This is a trait so tables can store
+/// columns for different types of components; the struct that actually stores components is
+/// `ComponentColumn`.
+///
+/// Rows are removed with `swap_remove`, like `Vec::swap_remove`: the last row takes the removed
+/// row's place, so removing a row is fast, but the order of rows changes.
+pub trait Column: AsAny {
+    /// Adds a component to the end of the column.
+    fn push(&mut self, component: Box<dyn Component>);
+    /// Replaces the component in a row.
+    fn set(&mut self, row: usize, component: Box<dyn Component>);
+    /// The column's components, as a type-erased `Vec<C>`. Queries borrow components from this
+    /// with `sync::downcast_index` and `sync::downcast_index_mut`.
+    fn data(&self) -> Shared<Lock<AnyData>>;
+    /// Removes and drops the component in a row.
+    fn swap_remove(&mut self, row: usize);
+    /// Removes the component in a row, and adds it to the end of another column. The other
+    /// column must store the same type of component.
+    fn move_row(&mut self, row: usize, other: &mut dyn Column);
+    /// The number of rows in the column.
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A function that makes an empty column for a particular component. See
+/// `Storage::column_builders`.
+pub type ColumnBuilder = fn() -> Box<dyn Column>;
+
+/// The struct that actually stores components in a column. See the `Column` trait.
+#[derive(Debug)]
+pub struct ComponentColumn<C: Component> {
+    components: Shared<Lock<Vec<C>>>,
+}
+impl<C: Component> Default for ComponentColumn<C> {
+    fn default() -> Self {
+        Self {
+            components: Shared::new(Lock::new(Vec::new())),
+        }
+    }
+}
+impl<C: Component> ComponentColumn<C> {
+    /// Makes a new, empty column. `Storage` keeps one of these functions for each component, so
+    /// it can make columns without knowing the component's type.
+    pub fn new_boxed() -> Box<dyn Column> {
+        Box::new(Self::default())
+    }
+
+    /// Writes a component into a row. If the column is one row short (see
+    /// `Storage::write_component`), the component is pushed instead.
+    pub fn write(&mut self, row: usize, component: C) {
+        let mut components = self.components.borrow_mut();
+        if components.len() > row {
+            components[row] = component;
+        } else {
+            components.push(component);
+        }
+    }
+
+    fn downcast(component: Box<dyn Component>) -> C {
+        *component
+            .as_any_owned()
+            .downcast()
+            .expect("Component was inserted into the wrong column")
+    }
+}
+impl<C: Component> Column for ComponentColumn<C> {
+    fn push(&mut self, component: Box<dyn Component>) {
+        self.components.borrow_mut().push(Self::downcast(component));
+    }
+    fn set(&mut self, row: usize, component: Box<dyn Component>) {
+        self.components.borrow_mut()[row] = Self::downcast(component);
+    }
+    fn data(&self) -> Shared<Lock<AnyData>> {
+        self.components.clone()
+    }
+    fn swap_remove(&mut self, row: usize) {
+        self.components.borrow_mut().swap_remove(row);
+    }
+    fn move_row(&mut self, row: usize, other: &mut dyn Column) {
+        let other: &mut Self = other
+            .as_any_mut()
+            .downcast_mut()
+            .expect("Tried to move a component into a column for a different component");
+        let component = self.components.borrow_mut().swap_remove(row);
+        other.components.borrow_mut().push(component);
+    }
+    fn len(&self) -> usize {
+        self.components.borrow().len()
+    }
+}
+
+/// Every entity with a particular set of components. See the module docs.
+pub struct Table {
+    /// The components entities in this table have, sorted by their `TypeId`.
+    pub components: Vec<TypeId>,
+    /// The column for each component, in the same order as `components`.
+    pub columns: Vec<Box<dyn Column>>,
+    /// The entity stored in each row.
+    pub entities: Vec<usize>,
+}
+impl Table {
+    /// The index of the column storing a component, if this table has that component.
+    pub fn column_idx(&self, component: TypeId) -> Option<usize> {
+        self.components.binary_search(&component).ok()
+    }
+    /// The column storing a component, if this table has that component.
+    pub fn column(&self, component: TypeId) -> Option<&dyn Column> {
+        self.column_idx(component)
+            .map(|idx| self.columns[idx].as_ref())
+    }
+    /// Mutable access to the column storing a component, if this table has that component.
+    pub fn column_mut(&mut self, component: TypeId) -> Option<&mut dyn Column> {
+        self.column_idx(component)
+            .map(|idx| self.columns[idx].as_mut())
+    }
+
+    /// If entities in this table have every component in `components`.
+    pub fn contains_all(&self, components: &[TypeId]) -> bool {
+        components
+            .iter()
+            .all(|component| self.column_idx(*component).is_some())
+    }
+
+    /// The number of entities in the table.
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}